@@ -0,0 +1,7 @@
+#!/bin/bash
+# Download the Vega scripts to bundle into the binary (--scripts inline|local).
+
+cd "$(dirname "$0")"
+curl -fsSL -o vega.min.js https://cdn.jsdelivr.net/npm/vega@5.17.0/build/vega.min.js
+curl -fsSL -o vega-lite.min.js https://cdn.jsdelivr.net/npm/vega-lite@4.17.0/build/vega-lite.min.js
+curl -fsSL -o vega-embed.min.js https://cdn.jsdelivr.net/npm/vega-embed@6.12.2/build/vega-embed.min.js
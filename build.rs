@@ -0,0 +1,35 @@
+//! Bundle the Vega scripts and the municipality boundaries into the
+//! binary when they are present in `assets/` (or the directory in
+//! `$VEGA_ASSETS`).
+
+use std::{env,fs};
+use std::path::PathBuf;
+
+const SCRIPTS: [&str; 3] = ["vega.min.js", "vega-lite.min.js", "vega-embed.min.js"];
+const BOUNDARIES: &str = "municipalities.topojson";
+
+fn main() {
+
+    let assets = env::var_os("VEGA_ASSETS").map_or_else(|| PathBuf::from("assets"), PathBuf::from);
+    println!("cargo:rerun-if-env-changed=VEGA_ASSETS");
+    println!("cargo:rerun-if-changed={}", assets.display());
+
+    let paths = SCRIPTS.iter().map(|name| fs::canonicalize(assets.join(name)))
+	.collect::<Result<Vec<_>,_>>();
+    let code = match paths {
+	Ok(paths) => format!("pub const SCRIPTS: Option<[(&str,&str); 3]> = Some([{}]);",
+			     SCRIPTS.iter().zip(&paths).map(
+				 |(name,path)| format!("({:?}, include_str!({:?}))", name, path)
+			     ).collect::<Vec<_>>().join(", ")),
+	Err(_) => "pub const SCRIPTS: Option<[(&str,&str); 3]> = None;".to_string()
+    };
+
+    let code = match fs::canonicalize(assets.join(BOUNDARIES)) {
+	Ok(path) => format!("{}\npub const BOUNDARIES: Option<&str> = Some(include_str!({:?}));", code, path),
+	Err(_) => format!("{}\npub const BOUNDARIES: Option<&str> = None;", code)
+    };
+
+    let out = PathBuf::from(env::var_os("OUT_DIR").expect("OUT_DIR not set"));
+    fs::write(out.join("assets.rs"), code).expect("cannot write assets.rs");
+
+}
@@ -0,0 +1,409 @@
+//! Alerts when a region crosses one of the reference levels. The
+//! level of each region is kept in a state file between runs; a
+//! change of level is reported to the configured notifiers.
+
+use std::{io,fs,fmt};
+use std::fs::{File,OpenOptions};
+use std::io::{Write,BufRead};
+use std::net::TcpStream;
+use std::path::{Path,PathBuf};
+use std::collections::BTreeMap;
+
+use chrono::Local;
+use serde::{Serialize,Deserialize};
+
+use super::error::{Result,Error};
+use super::report::level_name;
+
+
+/// Where to keep the levels between runs, and whom to notify.
+#[derive(Serialize,Deserialize,Clone,Debug)]
+pub struct AlertConfig {
+    #[serde(default = "default_state")]
+    pub state: PathBuf,
+    /// Relative margin below a level's threshold before a region
+    /// is considered to have dropped below it.
+    #[serde(default = "default_hysteresis")]
+    pub hysteresis: f64,
+    pub notifiers: Vec<Notifier>,
+}
+
+/// A destination for the alerts.
+#[derive(Serialize,Deserialize,Clone,Debug)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Notifier {
+    Stdout,
+    /// Append the alerts to a file, one per line.
+    File { path: PathBuf },
+    /// Mail the alerts through an SMTP relay ("host:port"), without
+    /// authentication or TLS.
+    Smtp { server: String, from: String, to: Vec<String> },
+    /// POST the alerts as a JSON array.
+    Webhook { url: String },
+}
+
+/// The current value of the tracked metric for a region.
+#[derive(Clone,Debug)]
+pub struct Metric {
+    pub region: String,
+    /// Date of the value, as YYYY-MM-DD.
+    pub date: String,
+    pub value: f64,
+}
+
+/// A region that moved to another level.
+#[derive(Serialize,Deserialize,Clone,Debug,PartialEq)]
+pub struct Event {
+    pub group: String,
+    pub var: String,
+    pub region: String,
+    pub date: String,
+    pub value: f64,
+    pub from: String,
+    pub to: String,
+    pub rising: bool,
+}
+
+/// Level of each region by group, as persisted between runs.
+type State = BTreeMap<String,BTreeMap<String,RegionState>>;
+
+#[derive(Serialize,Deserialize,Clone,Debug)]
+struct RegionState {
+    level: usize,
+    date: String,
+    value: f64,
+}
+
+
+/// Compare the `metrics` of a group to the increasing `levels`,
+/// notify the regions that changed level since the last run and
+/// update the state. Regions seen for the first time are only
+/// recorded.
+pub fn check(config: &AlertConfig, group: &str, var: &str,
+	     levels: &[(&str,f64)], metrics: &[Metric]) -> Result<Vec<Event>> {
+
+    let mut state = load_state(&config.state)?;
+    let regions = state.entry(group.to_string()).or_insert_with(BTreeMap::new);
+
+    let events = metrics.iter().filter_map(|metric| {
+	let previous = regions.get(&metric.region).map(|region| region.level);
+	let current = level(metric.value, levels, previous, config.hysteresis);
+	regions.insert(metric.region.clone(), RegionState {
+	    level: current, date: metric.date.clone(), value: metric.value
+	});
+	match previous {
+	    Some(previous) if previous != current => Some(Event {
+		group: group.to_string(),
+		var: var.to_string(),
+		region: metric.region.clone(),
+		date: metric.date.clone(),
+		value: metric.value,
+		from: level_name(levels, previous),
+		to: level_name(levels, current),
+		rising: current > previous
+	    }),
+	    _ => None
+	}
+    }).collect::<Vec<_>>();
+
+    if !events.is_empty() {
+	for notifier in &config.notifiers {
+	    if let Err(err) = notifier.notify(&events) {
+		println!("Warning: alert notification failed: {}", err);
+	    }
+	}
+    }
+
+    save_state(&config.state, &state)?;
+    Ok(events)
+
+}
+
+/// The level of `value`: the number of `levels` reached. Coming from
+/// the `previous` level, a level is only left downwards when the value
+/// falls `hysteresis` (relative) below its threshold.
+fn level(value: f64, levels: &[(&str,f64)], previous: Option<usize>, hysteresis: f64) -> usize {
+    let reached = levels.iter().filter(|(_,threshold)| value >= *threshold).count();
+    match previous {
+	Some(previous) if reached < previous => previous.min(levels.iter().filter(
+	    |(_,threshold)| value >= *threshold * (1.0 - hysteresis)
+	).count()),
+	_ => reached
+    }
+}
+
+fn load_state(path: &Path) -> Result<State> {
+    match File::open(path) {
+	Ok(file) => Ok(serde_json::from_reader(io::BufReader::new(file))?),
+	Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(State::new()),
+	Err(err) => Err(err.into())
+    }
+}
+
+fn save_state(path: &Path, state: &State) -> Result<()> {
+    if let Some(dir) = path.parent() {
+	fs::create_dir_all(dir)?;
+    }
+    serde_json::to_writer_pretty(io::BufWriter::new(File::create(path)?), state)?;
+    Ok(())
+}
+
+fn default_state() -> PathBuf {
+    PathBuf::from("alert-state.json")
+}
+
+fn default_hysteresis() -> f64 {
+    0.1
+}
+
+
+impl Notifier {
+
+    pub fn notify(&self, events: &[Event]) -> Result<()> {
+	match self {
+	    Self::Stdout => {
+		for event in events {
+		    println!("Alert: {}", event);
+		}
+		Ok(())
+	    },
+	    Self::File { path } => {
+		let mut out = OpenOptions::new().create(true).append(true).open(path)?;
+		for event in events {
+		    writeln!(out, "{}", event)?;
+		}
+		Ok(())
+	    },
+	    Self::Smtp { server, from, to } => send_mail(server, from, to, events),
+	    Self::Webhook { url } => {
+		let response = reqwest::blocking::Client::new().post(url).json(events).send()?;
+		match response.status().is_success() {
+		    true => Ok(()),
+		    false => Err(Error::HttpError(response.status()))
+		}
+	    }
+	}
+    }
+
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+	write!(f, "{} in {} ({}) {} from {} to {}: {:.3} on {}", self.var, self.region,
+	       self.group, match self.rising { true => "rose", false => "fell" },
+	       self.from, self.to, self.value, self.date)
+    }
+}
+
+
+/// Send the `events` as a plain text mail.
+fn send_mail(server: &str, from: &str, to: &[String], events: &[Event]) -> Result<()> {
+
+    let mut stream = TcpStream::connect(server)?;
+    let mut reader = io::BufReader::new(stream.try_clone()?);
+
+    smtp_reply(&mut reader, 220)?;
+    smtp_command(&mut stream, &mut reader, "HELO localhost", 250)?;
+    smtp_command(&mut stream, &mut reader, &format!("MAIL FROM:<{}>", from), 250)?;
+    for recipient in to {
+	smtp_command(&mut stream, &mut reader, &format!("RCPT TO:<{}>", recipient), 250)?;
+    }
+    smtp_command(&mut stream, &mut reader, "DATA", 354)?;
+
+    let mut message = format!("From: {}\r\nTo: {}\r\nDate: {}\r\n\
+			       Subject: COVID-19 alerts: {} level change(s)\r\n\
+			       Content-Type: text/plain; charset=UTF-8\r\n\r\n",
+			      from, to.join(", "), Local::now().to_rfc2822(), events.len());
+    for event in events {
+	let line = event.to_string();
+	match line.starts_with('.') {
+	    true => message.push_str(&format!(".{}\r\n", line)),
+	    false => message.push_str(&format!("{}\r\n", line))
+	}
+    }
+    message.push_str(".\r\n");
+    stream.write_all(message.as_bytes())?;
+    smtp_reply(&mut reader, 250)?;
+
+    smtp_command(&mut stream, &mut reader, "QUIT", 221)
+
+}
+
+fn smtp_command(stream: &mut TcpStream, reader: &mut impl BufRead,
+		command: &str, code: u16) -> Result<()> {
+    stream.write_all(format!("{}\r\n", command).as_bytes())?;
+    smtp_reply(reader, code)
+}
+
+/// Read a (possibly multiline) reply and check its code.
+fn smtp_reply(reader: &mut impl BufRead, code: u16) -> Result<()> {
+    loop {
+	let mut line = String::new();
+	if reader.read_line(&mut line)? == 0 {
+	    return Err(Error::Smtp("connection closed".to_string()));
+	}
+	if line.get(3..4) != Some("-") {
+	    return match line.get(..3).and_then(|c| c.parse::<u16>().ok()) == Some(code) {
+		true => Ok(()),
+		false => Err(Error::Smtp(line.trim_end().to_string()))
+	    };
+	}
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+    use std::thread;
+
+    const LEVELS: [(&str,f64); 2] = [("Niveau 3", 0.03), ("Niveau 4", 0.06)];
+
+    fn metric(region: &str, value: f64) -> Metric {
+	Metric { region: region.to_string(), date: "2020-10-01".to_string(), value }
+    }
+
+    fn config(name: &str, notifiers: Vec<Notifier>) -> AlertConfig {
+	let state = std::env::temp_dir().join(format!("covid19-alerts-{}-{}.json",
+							name, std::process::id()));
+	let _ = fs::remove_file(&state);
+	AlertConfig { state, hysteresis: 0.1, notifiers }
+    }
+
+    #[test]
+    fn hysteresis() {
+	assert_eq!(level(0.02, &LEVELS, None, 0.1), 0);
+	assert_eq!(level(0.031, &LEVELS, None, 0.1), 1);
+	assert_eq!(level(0.028, &LEVELS, Some(1), 0.1), 1);
+	assert_eq!(level(0.026, &LEVELS, Some(1), 0.1), 0);
+	assert_eq!(level(0.056, &LEVELS, Some(2), 0.1), 2);
+	assert_eq!(level(0.02, &LEVELS, Some(2), 0.1), 0);
+	assert_eq!(level(0.07, &LEVELS, Some(0), 0.1), 2);
+    }
+
+    #[test]
+    fn events_across_runs() {
+	let config = config("runs", vec![]);
+	let check = |values: &[(&str,f64)]| check(
+	    &config, "belgium/tests/region", "positivity", &LEVELS,
+	    &values.iter().map(|(region,value)| metric(region, *value)).collect::<Vec<_>>()
+	).unwrap();
+
+	assert!(check(&[("Brussels", 0.02), ("Flanders", 0.04)]).is_empty());
+	let events = check(&[("Brussels", 0.035), ("Flanders", 0.038)]);
+	assert_eq!(events.len(), 1);
+	assert_eq!((events[0].region.as_str(), events[0].to.as_str(), events[0].rising),
+		   ("Brussels", "Niveau 3", true));
+	assert!(check(&[("Brussels", 0.029), ("Flanders", 0.04)]).is_empty());
+	let events = check(&[("Brussels", 0.02), ("Flanders", 0.04)]);
+	assert_eq!((events[0].from.as_str(), events[0].to.as_str(), events[0].rising),
+		   ("Niveau 3", "below Niveau 3", false));
+
+	fs::remove_file(&config.state).unwrap();
+    }
+
+    #[test]
+    fn file_notifier() {
+	let path = std::env::temp_dir().join(format!("covid19-alerts-{}.log", std::process::id()));
+	let _ = fs::remove_file(&path);
+	let config = config("file", vec![Notifier::File { path: path.clone() }]);
+	check(&config, "g", "v", &LEVELS, &[metric("r", 0.0)]).unwrap();
+	check(&config, "g", "v", &LEVELS, &[metric("r", 0.1)]).unwrap();
+	let log = fs::read_to_string(&path).unwrap();
+	assert_eq!(log, "v in r (g) rose from below Niveau 3 to Niveau 4: 0.100 on 2020-10-01\n");
+	fs::remove_file(&path).unwrap();
+	fs::remove_file(&config.state).unwrap();
+    }
+
+    #[test]
+    fn smtp_notifier() {
+	let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+	let server = listener.local_addr().unwrap().to_string();
+	let relay = thread::spawn(move || {
+	    let (mut stream, _) = listener.accept().unwrap();
+	    let mut reader = io::BufReader::new(stream.try_clone().unwrap());
+	    let mut commands = vec![];
+	    let mut data = String::new();
+	    stream.write_all(b"220-localhost test relay\r\n220 ready\r\n").unwrap();
+	    loop {
+		let mut line = String::new();
+		reader.read_line(&mut line).unwrap();
+		let reply = match line.trim_end() {
+		    "DATA" => {
+			stream.write_all(b"354 go ahead\r\n").unwrap();
+			loop {
+			    let mut line = String::new();
+			    reader.read_line(&mut line).unwrap();
+			    if line == ".\r\n" { break; }
+			    data.push_str(&line);
+			}
+			"250 queued\r\n"
+		    },
+		    "QUIT" => "221 bye\r\n",
+		    _ => "250 ok\r\n"
+		};
+		stream.write_all(reply.as_bytes()).unwrap();
+		commands.push(line.trim_end().to_string());
+		if commands.last().unwrap() == "QUIT" {
+		    return (commands, data);
+		}
+	    }
+	});
+
+	let event = Event {
+	    group: "g".to_string(), var: "v".to_string(), region: "r".to_string(),
+	    date: "2020-10-01".to_string(), value: 0.04,
+	    from: "below Niveau 3".to_string(), to: "Niveau 3".to_string(), rising: true
+	};
+	Notifier::Smtp {
+	    server, from: "alerts@localhost".to_string(),
+	    to: vec!["a@localhost".to_string(), "b@localhost".to_string()]
+	}.notify(std::slice::from_ref(&event)).unwrap();
+
+	let (commands, data) = relay.join().unwrap();
+	assert_eq!(commands, vec!["HELO localhost", "MAIL FROM:<alerts@localhost>",
+				  "RCPT TO:<a@localhost>", "RCPT TO:<b@localhost>", "DATA", "QUIT"]);
+	assert!(data.contains("To: a@localhost, b@localhost\r\n"));
+	assert!(data.ends_with(&format!("\r\n\r\n{}\r\n", event)));
+    }
+
+    #[test]
+    fn webhook_notifier() {
+	let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+	let url = format!("http://{}/hook", listener.local_addr().unwrap());
+	let server = thread::spawn(move || {
+	    let (mut stream, _) = listener.accept().unwrap();
+	    let mut reader = io::BufReader::new(stream.try_clone().unwrap());
+	    let mut request = String::new();
+	    let mut length = 0;
+	    loop {
+		let mut line = String::new();
+		reader.read_line(&mut line).unwrap();
+		if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+		    length = value.trim().parse().unwrap();
+		}
+		if line == "\r\n" { break; }
+		request.push_str(&line);
+	    }
+	    let mut body = vec![0; length];
+	    reader.read_exact(&mut body).unwrap();
+	    stream.write_all(b"HTTP/1.1 204 No Content\r\nContent-Length: 0\r\n\r\n").unwrap();
+	    (request, body)
+	});
+
+	let event = Event {
+	    group: "g".to_string(), var: "v".to_string(), region: "r".to_string(),
+	    date: "2020-10-01".to_string(), value: 0.02,
+	    from: "Niveau 3".to_string(), to: "below Niveau 3".to_string(), rising: false
+	};
+	Notifier::Webhook { url }.notify(std::slice::from_ref(&event)).unwrap();
+
+	let (request, body) = server.join().unwrap();
+	assert!(request.starts_with("POST /hook HTTP/1.1\r\n"));
+	assert_eq!(serde_json::from_slice::<Vec<Event>>(&body).unwrap(), vec![event]);
+    }
+
+}
@@ -0,0 +1,63 @@
+//! On-disk cache policy shared by the source modules.
+
+use std::fs;
+use std::path::Path;
+
+use chrono::{DateTime,Local};
+
+use super::error::{Result,Error};
+
+
+/// How the source modules use their on-disk cache.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum CacheMode {
+    /// Download when the cached copy is older than the source's
+    /// maximum age.
+    Normal,
+    /// Always download, except for files that can no longer change.
+    Refresh,
+    /// Use any cached copy regardless of its age and never touch
+    /// the network.
+    Offline,
+}
+
+impl CacheMode {
+
+    /// Whether to use an existing cache file, given whether it is
+    /// still within the source's maximum age.
+    pub fn use_cached(&self, fresh: bool) -> bool {
+	match self {
+	    Self::Normal => fresh,
+	    Self::Refresh => false,
+	    Self::Offline => true
+	}
+    }
+
+    /// Check that `name` may be downloaded; fails in offline mode.
+    pub fn download(&self, name: &str) -> Result<()> {
+	match self {
+	    Self::Offline => Err(Error::NotCached(name.to_string())),
+	    _ => Ok(())
+	}
+    }
+
+    /// In offline mode, report how stale the cached `name` is.
+    pub fn report(&self, name: &str, cache_file: &Path) -> Result<()> {
+	if *self == Self::Offline {
+	    let modified : DateTime<Local> = fs::metadata(cache_file)?.modified()?.into();
+	    println!("Offline: using {} from {} ({} old)", name,
+		     modified.format("%Y-%m-%d %H:%M"), format_age(Local::now() - modified));
+	}
+	Ok(())
+    }
+
+}
+
+
+fn format_age(age: chrono::Duration) -> String {
+    match (age.num_days(), age.num_hours() % 24, age.num_minutes() % 60) {
+	(0, 0, m) => format!("{}m", m),
+	(0, h, m) => format!("{}h{:02}m", h, m),
+	(d, h, _) => format!("{}d{:02}h", d, h)
+    }
+}
@@ -0,0 +1,286 @@
+use std::path::PathBuf;
+
+use covid19_growth_rs::error::{Result,Error};
+use covid19_growth_rs::series::{GrowthMethod,Kernel};
+use covid19_growth_rs::forecast::ForecastModel;
+use covid19_growth_rs::graph::{Scripts,Image,Export};
+
+
+pub const USAGE: &str = "\
+Usage: covid19-growth-rs [OPTIONS] [COMMAND]
+
+Commands:
+    fetch                Only refresh the caches
+    render               Only build graphs, from the cached data
+                         (implies --offline)
+    (none)               Refresh outdated caches and build graphs
+
+Options:
+    --source LIST        Comma-separated list of sources (default: all):
+                         csse, sciensano-muni, sciensano-agesex,
+                         sciensano-hosp, sciensano-tests,
+                         sciensano-mort, sus;
+                         \"sciensano\" selects all Sciensano sources
+    --graph-dir DIR      Output directory for graphs (default: graphs)
+    --cache-dir DIR      Cache directory (default: cache)
+    --config FILE        Configuration file (default: config.json)
+    --smoothing LIST     Comma-separated smoothing windows in days
+                         (default: 1,7,14); \"adjusted\" adds daily
+                         counts adjusted for the day of the week
+    --redistribute DAYS  Spread negative daily case counts and sudden
+                         jumps over the preceding days (default: set
+                         negative counts to zero, report jumps)
+    --exclude-anomalies  Replace days flagged as anomalous (e.g. batch
+                         uploads) by the median of the surrounding days
+                         before smoothing
+    --forecast MODEL     Model for the 14-day projections of daily and
+                         active counts: growth (default), exponential
+                         or none
+    --kernel KERNEL      Smoothing kernel: trailing (default), centered,
+                         triangular or exponential
+    --growth LIST        Comma-separated growth estimators (default: ratio):
+                         ratio (of smoothed counts), regression
+                         (Poisson regression with confidence band)
+    --scripts MODE       Load the Vega scripts from the CDN (default),
+                         inline them in every page or load them from
+                         the local assets directory (cdn, inline, local)
+    --images LIST        Comma-separated static image formats to write
+                         next to every graph: svg, png (default: none;
+                         png requires the png feature)
+    --export LIST        Comma-separated data formats to write next to
+                         every graph, with the plotted values and
+                         reference lines: csv, json (default: none)
+    --offline            Only use cached data, regardless of its age,
+                         and never access the network
+    -h, --help           Show this help
+";
+
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Command {
+    Run,
+    Fetch,
+    Render,
+}
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Source {
+    Csse,
+    SciensanoMuni,
+    SciensanoAgeSex,
+    SciensanoHosp,
+    SciensanoTests,
+    SciensanoMort,
+    Sus,
+}
+
+#[derive(Debug)]
+pub struct Options {
+    pub command: Command,
+    pub sources: Vec<Source>,
+    pub graph_path: PathBuf,
+    pub cache_path: PathBuf,
+    pub config_path: PathBuf,
+    pub smoothings: Vec<usize>,
+    pub weekday_adjustment: bool,
+    pub redistribute: Option<usize>,
+    pub exclude_anomalies: bool,
+    pub forecast: Option<ForecastModel>,
+    pub kernel: Kernel,
+    pub growth_methods: Vec<GrowthMethod>,
+    pub scripts: Scripts,
+    pub images: Vec<Image>,
+    pub exports: Vec<Export>,
+    pub offline: bool,
+    pub help: bool,
+}
+
+
+impl Source {
+
+    pub const ALL: [Source; 7] = [
+	Self::Csse,
+	Self::SciensanoMuni,
+	Self::SciensanoAgeSex,
+	Self::SciensanoHosp,
+	Self::SciensanoTests,
+	Self::SciensanoMort,
+	Self::Sus,
+    ];
+
+    pub fn name(&self) -> &'static str {
+	match self {
+	    Self::Csse => "csse",
+	    Self::SciensanoMuni => "sciensano-muni",
+	    Self::SciensanoAgeSex => "sciensano-agesex",
+	    Self::SciensanoHosp => "sciensano-hosp",
+	    Self::SciensanoTests => "sciensano-tests",
+	    Self::SciensanoMort => "sciensano-mort",
+	    Self::Sus => "sus"
+	}
+    }
+
+    pub fn description(&self) -> &'static str {
+	match self {
+	    Self::Csse => "csse graphs",
+	    Self::SciensanoMuni => "sciensano municipality graphs",
+	    Self::SciensanoAgeSex => "sciensano agesex graphs",
+	    Self::SciensanoHosp => "sciensano hospitalization graphs",
+	    Self::SciensanoTests => "sciensano test graphs",
+	    Self::SciensanoMort => "sciensano mortality graphs",
+	    Self::Sus => "sus test graphs"
+	}
+    }
+
+    fn parse_list(list: &str) -> Result<Vec<Self>> {
+	let mut sources = Vec::new();
+	for name in list.split(',').map(|name| name.trim()) {
+	    let selected = match name {
+		"sciensano" => Self::ALL.iter().filter(
+		    |source| source.name().starts_with("sciensano-")
+		).cloned().collect(),
+		name => vec![*Self::ALL.iter().find(|source| source.name() == name)
+			     .ok_or_else(|| Error::InvalidArgument(
+				 format!("unknown source {:?}", name)))?]
+	    };
+	    for source in selected {
+		if !sources.contains(&source) {
+		    sources.push(source);
+		}
+	    }
+	}
+	Ok(sources)
+    }
+
+}
+
+
+impl Options {
+
+    pub fn parse<I>(args: I) -> Result<Self>
+    where I: IntoIterator<Item = String> {
+
+	let mut options = Self {
+	    command: Command::Run,
+	    sources: Source::ALL.to_vec(),
+	    graph_path: PathBuf::from("graphs"),
+	    cache_path: PathBuf::from("cache"),
+	    config_path: PathBuf::from("config.json"),
+	    smoothings: vec![1,7,14],
+	    weekday_adjustment: false,
+	    redistribute: None,
+	    exclude_anomalies: false,
+	    forecast: Some(ForecastModel::Growth),
+	    kernel: Kernel::Trailing,
+	    growth_methods: vec![GrowthMethod::Ratio],
+	    scripts: Scripts::Cdn,
+	    images: vec![],
+	    exports: vec![],
+	    offline: false,
+	    help: false,
+	};
+
+	let mut command = None;
+	let mut args = args.into_iter();
+
+	while let Some(arg) = args.next() {
+
+	    let (name,inline) = match arg.find('=') {
+		Some(i) if arg.starts_with("--") => (arg[..i].to_string(), Some(arg[i+1..].to_string())),
+		_ => (arg.clone(), None)
+	    };
+
+	    let mut value = || inline.clone().or_else(|| args.next()).ok_or_else(
+		|| Error::InvalidArgument(format!("missing value for {}", name)));
+
+	    match name.as_str() {
+		"-h" | "--help" => options.help = true,
+		"--source" => options.sources = Source::parse_list(&value()?)?,
+		"--graph-dir" => options.graph_path = PathBuf::from(value()?),
+		"--cache-dir" => options.cache_path = PathBuf::from(value()?),
+		"--config" => options.config_path = PathBuf::from(value()?),
+		"--smoothing" => {
+		    let (smoothings,adjusted) = parse_smoothings(&value()?)?;
+		    options.smoothings = smoothings;
+		    options.weekday_adjustment = adjusted;
+		},
+		"--redistribute" => options.redistribute = Some(value()?.parse()?),
+		"--exclude-anomalies" => options.exclude_anomalies = true,
+		"--forecast" => options.forecast = parse_forecast(&value()?)?,
+		"--kernel" => options.kernel = parse_kernel(&value()?)?,
+		"--growth" => options.growth_methods = parse_growth_methods(&value()?)?,
+		"--scripts" => options.scripts = parse_scripts(&value()?)?,
+		"--images" => options.images = parse_images(&value()?)?,
+		"--export" => options.exports = parse_exports(&value()?)?,
+		"--offline" => options.offline = true,
+		"fetch" if command.is_none() => command = Some(Command::Fetch),
+		"render" if command.is_none() => command = Some(Command::Render),
+		_ => return Err(Error::InvalidArgument(format!("unexpected argument {:?}", arg)))
+	    }
+
+	}
+
+	options.command = command.unwrap_or(Command::Run);
+
+	if options.offline && options.command == Command::Fetch {
+	    return Err(Error::InvalidArgument("cannot fetch in offline mode".to_string()));
+	}
+
+	Ok(options)
+
+    }
+
+}
+
+
+/// The smoothing windows, and whether "adjusted" is in the list.
+fn parse_smoothings(list: &str) -> Result<(Vec<usize>,bool)> {
+    let mut smoothings = Vec::new();
+    let mut adjusted = false;
+    for n in list.split(',').map(|n| n.trim()) {
+	match n {
+	    "adjusted" => adjusted = true,
+	    n => match n.parse()? {
+		0 => return Err(Error::InvalidArgument(format!("invalid smoothing {:?}", n))),
+		n => smoothings.push(n)
+	    }
+	}
+    }
+    Ok((smoothings, adjusted))
+}
+
+fn parse_forecast(name: &str) -> Result<Option<ForecastModel>> {
+    match name {
+	"none" => Ok(None),
+	name => ForecastModel::ALL.iter().find(|model| model.name() == name).map(|model| Some(*model))
+	    .ok_or_else(|| Error::InvalidArgument(format!("unknown forecast model {:?}", name)))
+    }
+}
+
+fn parse_kernel(name: &str) -> Result<Kernel> {
+    Kernel::ALL.iter().find(|kernel| kernel.name() == name).cloned().ok_or_else(
+	|| Error::InvalidArgument(format!("unknown kernel {:?}", name)))
+}
+
+fn parse_growth_methods(list: &str) -> Result<Vec<GrowthMethod>> {
+    list.split(',').map(|name| GrowthMethod::ALL.iter().find(
+	|method| method.name() == name.trim()
+    ).cloned().ok_or_else(|| Error::InvalidArgument(format!("unknown growth estimator {:?}", name)))).collect()
+}
+
+fn parse_scripts(name: &str) -> Result<Scripts> {
+    Scripts::ALL.iter().find(|scripts| scripts.name() == name).cloned().ok_or_else(
+	|| Error::InvalidArgument(format!("unknown scripts mode {:?}", name)))
+}
+
+fn parse_images(list: &str) -> Result<Vec<Image>> {
+    list.split(',').map(|name| Image::ALL.iter().find(
+	|image| image.name() == name.trim()
+    ).cloned().ok_or_else(|| Error::InvalidArgument(format!("unknown image format {:?}", name)))).collect()
+}
+
+fn parse_exports(list: &str) -> Result<Vec<Export>> {
+    list.split(',').map(|name| Export::ALL.iter().find(
+	|export| export.name() == name.trim()
+    ).cloned().ok_or_else(|| Error::InvalidArgument(format!("unknown export format {:?}", name)))).collect()
+}
@@ -0,0 +1,363 @@
+//! Configuration of the tracked regions and their populations.
+
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::collections::{BTreeMap,HashMap};
+
+use serde::{Serialize,Deserialize,Deserializer};
+
+use super::error::Result;
+use super::graph::Population;
+use super::sciensano::Level;
+use super::rt::SerialInterval;
+use super::alert::AlertConfig;
+
+
+/// Regions, populations and data keys for all sources, and the
+/// parameters of the estimates and alerts. Loaded from
+/// a JSON file; sections missing from the file fall back to the
+/// built-in defaults.
+#[derive(Serialize,Deserialize,Debug)]
+#[serde(default)]
+pub struct Config {
+    pub csse: BTreeMap<String,Vec<CsseRegion>>,
+    pub sciensano: SciensanoConfig,
+    pub sus: SusConfig,
+    /// Serial interval distribution for the Rt estimates.
+    pub serial_interval: SerialInterval,
+    /// Alerts on reference level crossings; disabled when missing.
+    pub alerts: Option<AlertConfig>,
+}
+
+/// A region in a CSSE group, summed over one or more CSSE keys
+/// ("Country" or "Province/State,Country").
+#[derive(Serialize,Deserialize,Debug)]
+pub struct CsseRegion {
+    pub name: String,
+    pub population: u64,
+    pub keys: Vec<String>,
+}
+
+#[derive(Serialize,Deserialize,Debug)]
+#[serde(default)]
+pub struct SciensanoConfig {
+    /// Municipality (or district, province, ...) graphs, by level.
+    pub muni: BTreeMap<Level,Population>,
+    /// Population by group ("country", "province", "region", "age",
+    /// "sex", "age-F", "age-M" and "age-mort"). Groups in the file
+    /// replace the built-in group of the same name; the other
+    /// built-in groups are kept.
+    #[serde(deserialize_with = "merge_population")]
+    pub population: HashMap<String,Population>,
+}
+
+#[derive(Serialize,Deserialize,Debug)]
+#[serde(default)]
+pub struct SusConfig {
+    pub estados: Vec<Estado>,
+    pub municipios: Vec<Municipio>,
+}
+
+#[derive(Serialize,Deserialize,Debug)]
+pub struct Estado {
+    pub codigo: String,
+    pub nome: String,
+}
+
+#[derive(Serialize,Deserialize,Debug)]
+pub struct Municipio {
+    pub estado: String,
+    pub nome: String,
+}
+
+
+impl Config {
+
+    /// Load the configuration from `path`, or use the defaults
+    /// if the file does not exist. Fails on an invalid serial
+    /// interval.
+    pub fn load(path: &Path) -> Result<Self> {
+	match File::open(path) {
+	    Ok(file) => {
+		let config : Self = serde_json::from_reader(io::BufReader::new(file))?;
+		config.serial_interval.validate()?;
+		Ok(config)
+	    },
+	    Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+	    Err(err) => Err(err.into())
+	}
+    }
+
+}
+
+
+impl Default for Config {
+    fn default() -> Self {
+	Self {
+	    csse: default_csse(),
+	    sciensano: SciensanoConfig::default(),
+	    sus: SusConfig::default(),
+	    serial_interval: SerialInterval::default(),
+	    alerts: None,
+	}
+    }
+}
+
+impl Default for SciensanoConfig {
+    fn default() -> Self {
+	Self {
+	    muni: vec![
+		(Level::Municipality, population(vec![
+		    ("Scherpenheuvel-Zichem", 23078),
+		    ("Holsbeek",              10062),
+		    ("Aarschot",              30183),
+		    ("Kortrijk",              77109),
+		    ("Herselt",               14521),
+		    ("Wervik",                18909),
+		    ("Leuven",               102275),
+		    ("Brussel",              185103),
+		    ("Mechelen",              86921),
+		    ("Antwerpen",            529247),
+		    ("Gent",                 263927),
+		    ("Tienen",                35293),
+		    ("Hasselt",               78714),
+		    ("Sint-Truiden",          40672),
+		    ("Westerlo",              25119),
+		    ("Heist-op-den-Berg",     42950),
+		]))
+	    ].into_iter().collect(),
+	    population: vec![
+		("country", population(vec![
+		    ("Belgium", 11000638)
+		])),
+		("province", population(vec![
+		    ("Antwerpen",      1869730),
+		    ("BrabantWallon",   406019),
+		    ("Brussels",       1218255),
+		    ("Hainaut",        1346840),
+		    ("Limburg",         877370),
+		    ("Liège",          1109800),
+		    ("Luxembourg",      286752),
+		    ("Namur",           495832),
+		    ("OostVlaanderen", 1525255),
+		    ("VlaamsBrabant",  1155843),
+		    ("WestVlaanderen", 1200945),
+		])),
+		("region", population(vec![
+		    ("Brussels", 1218255),
+		    ("Flanders", 6629143),
+		    ("Wallonia", 3645243),
+		])),
+		("age", population(vec![
+		    ("0-9",   1269068),
+		    ("10-19", 1300254),
+		    ("20-29", 1407645),
+		    ("30-39", 1492290),
+		    ("40-49", 1504539),
+		    ("50-59", 1590628),
+		    ("60-69", 1347139),
+		    ("70-79",  924291),
+		    ("80-89",  539390),
+		    ("90+",    117397),
+		])),
+		("sex", population(vec![
+		    ("F", 5815933),
+		    ("M", 5676708),
+		])),
+		("age-F", population(vec![
+		    ("0-9",   617857),
+		    ("10-19", 631735),
+		    ("20-29", 693806),
+		    ("30-39", 743019),
+		    ("40-49", 740053),
+		    ("50-59", 788789),
+		    ("60-69", 686981),
+		    ("70-79", 500120),
+		    ("80-89", 327063),
+		    ("90+",    86510),
+		])),
+		("age-M", population(vec![
+		    ("0-9",   651211),
+		    ("10-19", 668519),
+		    ("20-29", 713839),
+		    ("30-39", 749271),
+		    ("40-49", 764486),
+		    ("50-59", 801839),
+		    ("60-69", 660158),
+		    ("70-79", 424171),
+		    ("80-89", 212327),
+		    ("90+",    30887),
+		])),
+		("age-mort", population(vec![
+		    ("0-24",  3224743),
+		    ("25-44", 2990406),
+		    ("45-64", 3069121),
+		    ("65-74", 1182105),
+		    ("75-84",  680086),
+		    ("85+",    346180),
+		]))
+	    ].into_iter().map(|(group,population)| (group.to_string(), population)).collect()
+	}
+    }
+}
+
+impl Default for SusConfig {
+    fn default() -> Self {
+	Self {
+	    estados: vec![
+		("AC", "Acre"),
+		("AL", "Alagoas"),
+		("AP", "Amapá"),
+		("AM", "Amazonas"),
+		("BA", "Bahia"),
+		("CE", "Ceará"),
+		("DF", "Distrito Federal"),
+		("ES", "Espírito Santo"),
+		("GO", "Goiás"),
+		("MA", "Maranhão"),
+		("MT", "Mato Grosso"),
+		("MS", "Mato Grosso do Sul"),
+		("MG", "Minas Gerais"),
+		("PA", "Pará"),
+		("PB", "Paraíba"),
+		("PR", "Paraná"),
+		("PE", "Pernambuco"),
+		("PI", "Piauí"),
+		("RJ", "Rio de Janeiro"),
+		("RN", "Rio Grande do Norte"),
+		("RS", "Rio Grande do Sul"),
+		("RO", "Rondônia"),
+		("RR", "Roraima"),
+		("SC", "Santa Catarina"),
+		("SP", "São Paulo"),
+		("SE", "Sergipe"),
+		("TO", "Tocantins")
+	    ].into_iter().map(|(codigo,nome)| Estado {
+		codigo: codigo.to_string(), nome: nome.to_string()
+	    }).collect(),
+	    municipios: vec![
+		("CE", "Fortaleza"),
+		("CE", "Canindé"),
+		("MT", "Barra do Garças")
+	    ].into_iter().map(|(estado,nome)| Municipio {
+		estado: estado.to_string(), nome: nome.to_string()
+	    }).collect()
+	}
+    }
+}
+
+
+fn default_csse() -> BTreeMap<String,Vec<CsseRegion>> {
+    vec![
+	("europe", vec![
+	    ("Italy",          60062012, vec!["Italy"]),
+	    ("Spain",          47329981, vec!["Spain"]),
+	    ("Belgium",        11535652, vec!["Belgium"]),
+	    ("Netherlands",    17523131, vec!["Netherlands"]),
+	    ("Romania",        19317984, vec!["Romania"]),
+	    ("Switzerland",     8632703, vec!["Switzerland"]),
+	    ("Austria",         8915382, vec!["Austria"]),
+	    ("France",         67132000, vec!["France"]),
+	    ("Germany",        83122889, vec!["Germany"]),
+	    ("Sweden",         10367232, vec!["Sweden"]),
+	    ("Norway",          5374807, vec!["Norway"]),
+	    ("Finland",         5503335, vec!["Finland"]),
+	    ("United Kingdom", 66796807, vec!["United Kingdom"]),
+	    ("Portugal",       10295909, vec!["Portugal"]),
+	]),
+	("america", vec![
+	    ("Brazil",   212245791, vec!["Brazil"]),
+	    ("Chile",     19458310, vec!["Chile"]),
+	    ("Peru",      32625948, vec!["Peru"]),
+	    ("Argentina", 45376763, vec!["Argentina"]),
+	    ("Ecuador",   17595980, vec!["Ecuador"]),
+	    ("Bolivia",   11633371, vec!["Bolivia"]),
+	    ("Colombia",  50372424, vec!["Colombia"]),
+	    ("Mexico",   127792286, vec!["Mexico"]),
+	    ("US",       330533177, vec!["US"]),
+	    ("Canada",    38220052, vec![
+		"Northwest Territories,Canada", "Saskatchewan,Canada",
+		"Prince Edward Island,Canada", "Alberta,Canada",
+		"Nova Scotia,Canada", "Yukon,Canada", "British Columbia,Canada",
+		"Newfoundland and Labrador,Canada", "New Brunswick,Canada",
+		"Ontario,Canada", "Quebec,Canada", "Manitoba,Canada"])]),
+	("africa", vec![
+	    ("South Africa",      59622350, vec!["South Africa"]),
+	    ("Congo (Kinshasa)", 101935800, vec!["Congo (Kinshasa)"]),
+	    ("Ghana",             30955202, vec!["Ghana"]),
+	    ("Egypt",            101092069, vec!["Egypt"]),
+	    ("Israel",             9268700, vec!["Israel"])]),
+	("rest", vec![
+	    ("South Korea",  51841786, vec!["Korea, South"]),
+	    ("Japan",       125880000, vec!["Japan"]),
+	    ("Russia",      146748590, vec!["Russia"]),
+	    ("India",      1368830362, vec!["India"]),
+	    ("China",      1405021280, vec![
+		"Anhui,China", "Xinjiang,China", "Henan,China",
+		"Shaanxi,China", "Hunan,China", "Jiangxi,China",
+		"Zhejiang,China", "Shanxi,China", "Tibet,China",
+		"Shanghai,China", "Macau,China", "Beijing,China",
+		"Jilin,China", "Tianjin,China", "Fujian,China",
+		"Guizhou,China", "Heilongjiang,China", "Gansu,China",
+		"Hainan,China", "Guangdong,China", "Hubei,China",
+		"Qinghai,China", "Sichuan,China", "Ningxia,China",
+		"Shandong,China", "Hebei,China", "Inner Mongolia,China",
+		"Chongqing,China", "Guangxi,China", "Liaoning,China",
+		"Yunnan,China", "Jiangsu,China", "Hong Kong,China"]),
+	    ("Australia",    25686212, vec![
+		"South Australia,Australia",
+		"Australian Capital Territory,Australia",
+		"New South Wales,Australia",
+		"Victoria,Australia",
+		"Western Australia,Australia",
+		"Queensland,Australia",
+		"Northern Territory,Australia",
+		"Tasmania,Australia"]),
+	    ("Iran",         83893073, vec!["Iran"]),
+	    ("Iraq",         40150200, vec!["Iraq"]),
+	    ("Turkey",       83154997, vec!["Turkey"])])
+    ].into_iter().map(|(group,regions)| (group.to_string(), regions.into_iter().map(
+	|(name,population,keys)| CsseRegion {
+	    name: name.to_string(),
+	    population,
+	    keys: keys.into_iter().map(|key| key.to_string()).collect()
+	}).collect())).collect()
+}
+
+
+/// The population groups in the configuration file over the
+/// built-in ones.
+fn merge_population<'de, D>(deserializer: D) -> std::result::Result<HashMap<String,Population>, D::Error>
+where D: Deserializer<'de> {
+    let mut population = SciensanoConfig::default().population;
+    population.extend(HashMap::<String,Population>::deserialize(deserializer)?);
+    Ok(population)
+}
+
+fn population(data: Vec<(&str,u64)>) -> Population {
+    data.into_iter().map(|(region,n)| (region.to_string(), n)).collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn readme_example() {
+	let readme = include_str!("../README.md");
+	let start = readme.find("```json\n").unwrap() + "```json\n".len();
+	let end = start + readme[start..].find("```").unwrap();
+	let config : Config = serde_json::from_str(&readme[start..end]).unwrap();
+	let population = &config.sciensano.population;
+	assert_eq!(population["region"].len(), 3);
+	for group in &["country", "province", "region", "age", "sex", "age-F", "age-M", "age-mort"] {
+	    assert!(population.contains_key(*group), "missing {}", group);
+	}
+	assert_eq!(config.sciensano.muni[&Level::Municipality].len(), 2);
+	assert_eq!(config.sus.estados.len(), 1);
+    }
+
+}
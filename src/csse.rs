@@ -1,3 +1,5 @@
+//! Johns Hopkins CSSE global time series.
+
 use std::{fs,io};
 use std::fs::File;
 use std::path::Path;
@@ -7,31 +9,34 @@ use std::collections::HashMap;
 use chrono::naive::NaiveDate;
 
 use super::error::Result;
-use super::graph::Series;
-use super::NaiveDateRange;
+use super::cache::CacheMode;
+use super::series::Series;
 
 
-pub fn confirmed(cache_path: &Path) -> Result<HashMap<String,Series>> {
+/// Cumulative confirmed cases by CSSE key ("Country" or
+/// "Province/State,Country").
+pub fn confirmed(cache_path: &Path, mode: CacheMode) -> Result<HashMap<String,Series>> {
 
     let cache_path = cache_path.join("csse");
     let cache_file = cache_path.join("confirmed.json");
 
-    if cache_file.exists() && fs::metadata(&cache_file)?.modified()?.elapsed()? < Duration::new(1800,0) {
+    if cache_file.exists() && mode.use_cached(
+	fs::metadata(&cache_file)?.modified()?.elapsed()? < Duration::new(1800,0)) {
 	let contents = serde_json::from_reader::<_,HashMap<String,Vec<f64>>>(
 	    io::BufReader::new(File::open(&cache_file)?));
 	if let Ok(cached) = contents {
+	    mode.report("csse/confirmed.json", &cache_file)?;
 	    return Ok(cached.into_iter().map(
-		|(n,s)| (n, NaiveDateRange(NaiveDate::from_ymd(2020, 1, 22), None)
-			 .zip(s).collect())).collect());
+		|(n,s)| (n, Series::from_values(NaiveDate::from_ymd(2020, 1, 22), s))).collect());
 	}
     }
 
+    mode.download("csse/confirmed.json")?;
     let data = download_confirmed()?;
     fs::create_dir_all(&cache_path)?;
     serde_json::to_writer(io::BufWriter::new(File::create(cache_file)?), &data)?;
     Ok(data.into_iter().map(
-	|(n,s)| (n, NaiveDateRange(NaiveDate::from_ymd(2020, 1, 22), None)
-		 .zip(s).collect())).collect())
+	|(n,s)| (n, Series::from_values(NaiveDate::from_ymd(2020, 1, 22), s))).collect())
 
 }
 
@@ -14,9 +14,15 @@ pub enum Error {
     SystemTime(time::SystemTimeError),
     ParseInt(num::ParseIntError),
     ParseDate(chrono::format::ParseError),
-    MissingRegion(&'static str),
+    MissingRegion(String),
     MissingData,
     ESQueryFailed(String),
+    InvalidArgument(String),
+    InvalidConfig(String),
+    NotCached(String),
+    Smtp(String),
+    Render(String),
+    InvalidBoundaries(String),
 }
 
 impl From<io::Error> for Error {
@@ -76,6 +82,12 @@ impl fmt::Display for Error {
 	    Self::MissingRegion(name) => write!(f, "Missing region: {}", name),
 	    Self::MissingData => write!(f, "No data!"),
 	    Self::ESQueryFailed(e) => write!(f, "Elasticsearch query failed: {}", e),
+	    Self::InvalidArgument(e) => write!(f, "Invalid argument: {}", e),
+	    Self::InvalidConfig(e) => write!(f, "Invalid configuration: {}", e),
+	    Self::NotCached(name) => write!(f, "Not in cache (offline): {}", name),
+	    Self::Smtp(reply) => write!(f, "SMTP error: {}", reply),
+	    Self::Render(err) => write!(f, "Render error: {}", err),
+	    Self::InvalidBoundaries(err) => write!(f, "Invalid boundaries: {}", err),
 	}
     }
 }
@@ -0,0 +1,215 @@
+//! Export of the plotted values of the graphs as CSV and JSON files
+//! next to the pages, for use in spreadsheets and statistics packages.
+//!
+//! Both formats hold the same rows, one per plotted point, in long
+//! format: the `kind` of layer ("data", "band", "forecast", "anomaly"
+//! or "reference"), the date (none for reference lines), the region
+//! (the label of a reference line) and the value and/or bounds.
+
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use serde::Serialize;
+use serde_json::json;
+use chrono::naive::NaiveDate;
+
+use super::error::Result;
+use super::series::Series;
+use super::graph::{CasesData,TestsData,MapData,WeeklyData,PyramidData,Refs,Overlays,Export};
+
+
+#[derive(Serialize,Debug)]
+struct Row {
+    kind: &'static str,
+    date: Option<String>,
+    region: String,
+    value: Option<f64>,
+    lower: Option<f64>,
+    upper: Option<f64>,
+}
+
+
+/// Write the values of a line graph (as drawn by `graph::graph_overlays`)
+/// as `name.csv` and/or `name.json` in `graph_path`.
+#[allow(clippy::too_many_arguments)]
+pub fn lines(graph_path: &Path, name: &str, title: &str, ytitle: &str, refs: &Refs,
+	     data: &CasesData, overlays: &Overlays, exports: &[Export]) -> Result<()> {
+
+    let mut rows = vec![];
+
+    for (region,series) in data {
+	rows.extend(series.present().filter(|(_,val)| val.is_finite()).map(
+	    |(date,val)| Row {
+		kind: "data",
+		date: Some(format!("{}", date.format("%Y-%m-%d"))),
+		region: region.to_string(),
+		value: Some(*val),
+		lower: None,
+		upper: None
+	    }));
+    }
+
+    for (region,series) in &overlays.bands {
+	rows.extend(series.present().filter(|(_,val)| val.lower.is_finite() && val.upper.is_finite()).map(
+	    |(date,val)| Row {
+		kind: "band",
+		date: Some(format!("{}", date.format("%Y-%m-%d"))),
+		region: region.to_string(),
+		value: None,
+		lower: Some(val.lower),
+		upper: Some(val.upper)
+	    }));
+    }
+
+    for (region,series) in &overlays.forecast {
+	rows.extend(series.present().filter(
+	    |(_,val)| val.value.is_finite() && val.lower.is_finite() && val.upper.is_finite()
+	).map(
+	    |(date,val)| Row {
+		kind: "forecast",
+		date: Some(format!("{}", date.format("%Y-%m-%d"))),
+		region: region.to_string(),
+		value: Some(val.value),
+		lower: Some(val.lower),
+		upper: Some(val.upper)
+	    }));
+    }
+
+    for (region,dates) in &overlays.anomalies {
+	let vals = data.iter().find(|(r,_)| r == region).map(|(_,vals)| vals);
+	rows.extend(dates.iter().filter_map(
+	    |date| vals?.get(*date).filter(|val| val.is_finite()).map(
+		|val| Row {
+		    kind: "anomaly",
+		    date: Some(format!("{}", date.format("%Y-%m-%d"))),
+		    region: region.to_string(),
+		    value: Some(*val),
+		    lower: None,
+		    upper: None
+		})));
+    }
+
+    write(graph_path, name, title, ytitle, rows, refs, exports)
+
+}
+
+
+/// Write the values of a test graph (as drawn by `graph::graph_tests`)
+/// as `name.csv` and/or `name.json` in `graph_path`, with the total
+/// number of tests and the positivity ratio as regions.
+pub fn tests(graph_path: &Path, name: &str, title: &str, data: &TestsData,
+	     refs: &Refs, exports: &[Export]) -> Result<()> {
+
+    let rows = data.present().filter(
+	|(_,tests)| tests.positive + tests.negative != 0.0
+    ).flat_map(|(date,tests)| vec![
+	("Total", tests.total),
+	("Positive", 1f64.min(tests.positivity()))
+    ].into_iter().map(move |(region,val)| Row {
+	kind: "data",
+	date: Some(format!("{}", date.format("%Y-%m-%d"))),
+	region: region.to_string(),
+	value: Some(val),
+	lower: None,
+	upper: None
+    })).collect();
+
+    write(graph_path, name, title, "Tests", rows, refs, exports)
+
+}
+
+
+/// Write the values of a map (as drawn by `graph::map_graph`) on
+/// `dates` as `name.csv` and/or `name.json` in `graph_path`, by
+/// region rather than by municipality.
+pub fn map(graph_path: &Path, name: &str, title: &str, dates: &[NaiveDate], data: &MapData,
+	   refs: &Refs, exports: &[Export]) -> Result<()> {
+
+    let rows = on_dates(dates, data.iter().map(|(region,_,series)| (region.to_string(), series)));
+    write(graph_path, name, title, "Incidence", rows, refs, exports)
+}
+
+
+/// Write the values of a pyramid (as drawn by `graph::pyramid_graph`)
+/// on `dates` as `name.csv` and/or `name.json` in `graph_path`, with
+/// the age group and sex as region.
+pub fn pyramid(graph_path: &Path, name: &str, title: &str, dates: &[NaiveDate], data: &PyramidData,
+	       exports: &[Export]) -> Result<()> {
+    let rows = on_dates(dates, data.iter().map(|(age,sex,series)| (format!("{} {}", age, sex), series)));
+    write(graph_path, name, title, "Incidence", rows, &vec![], exports)
+}
+
+
+/// Write the values of a heatmap (as drawn by `graph::heatmap`) as
+/// `name.csv` and/or `name.json` in `graph_path`, dated by the Monday
+/// of each week, with the rows as regions.
+pub fn weekly(graph_path: &Path, name: &str, title: &str, ytitle: &str, data: &WeeklyData,
+	      exports: &[Export]) -> Result<()> {
+
+    let rows = data.iter().flat_map(|(row,weeks)| weeks.iter().filter(|(_,val)| val.is_finite()).map(
+	move |(monday,val)| Row {
+	    kind: "data",
+	    date: Some(format!("{}", monday.format("%Y-%m-%d"))),
+	    region: row.to_string(),
+	    value: Some(*val),
+	    lower: None,
+	    upper: None
+	})).collect();
+
+    write(graph_path, name, title, ytitle, rows, &vec![], exports)
+
+}
+
+
+/// The values of `data` (by region) on `dates`.
+fn on_dates<'a>(dates: &[NaiveDate], data: impl Iterator<Item = (String,&'a Series)>) -> Vec<Row> {
+    let data = data.collect::<Vec<_>>();
+    dates.iter().flat_map(|date| data.iter().filter_map(
+	move |(region,series)| series.get(*date).filter(|val| val.is_finite()).map(
+	    |val| Row {
+		kind: "data",
+		date: Some(format!("{}", date.format("%Y-%m-%d"))),
+		region: region.clone(),
+		value: Some(*val),
+		lower: None,
+		upper: None
+	    })
+    )).collect()
+}
+
+
+fn write(graph_path: &Path, name: &str, title: &str, ytitle: &str,
+	 mut rows: Vec<Row>, refs: &Refs, exports: &[Export]) -> Result<()> {
+
+    rows.extend(refs.iter().map(|(label,y)| Row {
+	kind: "reference",
+	date: None,
+	region: label.unwrap_or("").to_string(),
+	value: Some(*y),
+	lower: None,
+	upper: None
+    }));
+
+    for export in exports {
+	let path = graph_path.join(format!("{}.{}", name, export.name()));
+	match export {
+	    Export::Csv => {
+		let mut out = csv::Writer::from_path(path)?;
+		for row in &rows {
+		    out.serialize(row)?;
+		}
+		out.flush()?;
+	    },
+	    Export::Json => serde_json::to_writer_pretty(
+		io::BufWriter::new(File::create(path)?), &json!({
+		    "title": title,
+		    "ytitle": ytitle,
+		    "values": rows
+		}))?
+	}
+    }
+
+    Ok(())
+
+}
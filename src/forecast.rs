@@ -0,0 +1,178 @@
+//! Short-term projections of daily series, with prediction intervals.
+
+use chrono::Duration;
+
+use super::series::{Series,Estimate,PoissonFit,poisson_fit};
+
+
+/// Values needed by the exponential model: two to start the level
+/// and trend, and one more for the variance of the errors.
+const EXPONENTIAL_MIN: usize = 3;
+
+
+/// Model used to project a series.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum ForecastModel {
+    /// Continue the current growth: a Poisson log-linear regression
+    /// over the last days.
+    Growth,
+    /// Holt's linear exponential smoothing of the log values.
+    Exponential,
+}
+
+impl ForecastModel {
+
+    pub const ALL: [ForecastModel; 2] = [
+	Self::Growth,
+	Self::Exponential,
+    ];
+
+    pub fn name(&self) -> &'static str {
+	match self {
+	    Self::Growth => "growth",
+	    Self::Exponential => "exponential"
+	}
+    }
+
+}
+
+
+/// Project `data` `horizon` days beyond its last value, with a 95%
+/// prediction interval. The growth model is fitted on the last
+/// `window` days, the exponential model on all days since the last
+/// missing value. The result is empty if the model cannot be fitted.
+pub fn forecast(data: &Series, model: ForecastModel, window: usize,
+		horizon: usize) -> Series<Estimate> {
+
+    let values = data.values();
+    let end = match values.iter().rposition(Option::is_some) {
+	Some(end) => end,
+	None => return Series::new(data.start(), vec![])
+    };
+    let begin = values[..end].iter().rposition(Option::is_none).map_or(0, |i| i + 1);
+    let recent = values[begin..=end].iter().flatten().map(|v| v.max(0.0)).collect::<Vec<_>>();
+    let start = data.start() + Duration::days(end as i64 + 1);
+
+    let projection = match model {
+	ForecastModel::Growth if recent.len() >= window =>
+	    growth(&recent[recent.len()-window..], horizon),
+	ForecastModel::Exponential if recent.len() >= EXPONENTIAL_MIN =>
+	    exponential(&recent, horizon),
+	_ => None
+    };
+
+    Series::from_values(start, projection.unwrap_or_default())
+
+}
+
+
+/// Extrapolate the Poisson regression on `counts`. The interval
+/// combines the uncertainty of the fit with the (overdispersed)
+/// variation of the counts, on the log scale.
+fn growth(counts: &[f64], horizon: usize) -> Option<Vec<Estimate>> {
+    let fit = poisson_fit(counts)?;
+    Some((1..=horizon).map(|h| {
+	let x = PoissonFit::x((counts.len() - 1 + h) as f64, counts.len());
+	let eta = fit.a + fit.b * x;
+	let var = fit.cov[0][0] + x * x * fit.cov[1][1] + 2.0 * x * fit.cov[0][1]
+	    + fit.dispersion / eta.exp().max(1.0);
+	Estimate {
+	    value: eta.exp(),
+	    lower: (eta - 1.96 * var.sqrt()).exp(),
+	    upper: (eta + 1.96 * var.sqrt()).exp()
+	}
+    }).collect())
+}
+
+
+/// Holt's linear method on log(1 + count), with the smoothing
+/// parameters that minimize the one-step-ahead squared error. Needs
+/// at least `EXPONENTIAL_MIN` counts (checked by `forecast`).
+fn exponential(counts: &[f64], horizon: usize) -> Option<Vec<Estimate>> {
+
+    debug_assert!(counts.len() >= EXPONENTIAL_MIN);
+
+    let logs = &counts.iter().map(|c| c.ln_1p()).collect::<Vec<_>>();
+    let grid = (1..10).map(|i| i as f64 / 10.0).collect::<Vec<_>>();
+
+    let (alpha,beta,(level,trend,sse)) = grid.iter().flat_map(
+	|alpha| grid.iter().map(move |beta| (*alpha, *beta, holt(logs, *alpha, *beta)))
+    ).min_by(|a,b| (a.2).2.partial_cmp(&(b.2).2).unwrap_or(std::cmp::Ordering::Equal))?;
+
+    let sigma2 = sse / (logs.len() - 2) as f64;
+    Some((1..=horizon).map(|h| {
+	let mean = level + h as f64 * trend;
+	let var = sigma2 * (1.0 + (1..h).map(
+	    |j| (alpha * (1.0 + j as f64 * beta)).powi(2)
+	).sum::<f64>());
+	Estimate {
+	    value: mean.exp_m1().max(0.0),
+	    lower: (mean - 1.96 * var.sqrt()).exp_m1().max(0.0),
+	    upper: (mean + 1.96 * var.sqrt()).exp_m1().max(0.0)
+	}
+    }).collect())
+
+}
+
+/// Final level and trend of Holt's method, and the sum of squared
+/// one-step-ahead errors.
+fn holt(values: &[f64], alpha: f64, beta: f64) -> (f64, f64, f64) {
+    let mut level = values[0];
+    let mut trend = values[1] - values[0];
+    let mut sse = 0.0;
+    for value in &values[1..] {
+	let error = value - (level + trend);
+	sse += error * error;
+	let next = level + trend + alpha * error;
+	trend += alpha * beta * error;
+	level = next;
+    }
+    (level, trend, sse)
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn date(day: u32) -> NaiveDate {
+	NaiveDate::from_ymd(2020, 10, day)
+    }
+
+    fn exponential_series(days: usize) -> Series {
+	Series::from_values(date(1), (0..days).map(|i| 100.0 * (0.1 * i as f64).exp()))
+    }
+
+    #[test]
+    fn continues_exponential() {
+	let data = exponential_series(14);
+	for model in &ForecastModel::ALL {
+	    let projection = forecast(&data, *model, 14, 7);
+	    assert_eq!(projection.start(), date(15));
+	    assert_eq!(projection.len(), 7);
+	    for (i,(_,estimate)) in projection.present().enumerate() {
+		let expected = 100.0 * (0.1 * (14 + i) as f64).exp();
+		assert!((estimate.value / expected - 1.0).abs() < 0.01,
+			"{}: {} != {}", model.name(), estimate.value, expected);
+		assert!(estimate.lower <= estimate.value && estimate.upper >= estimate.value);
+	    }
+	}
+    }
+
+    #[test]
+    fn missing_data() {
+	let data = Series::new(date(1), vec![None; 20]);
+	for model in &ForecastModel::ALL {
+	    assert!(forecast(&data, *model, 14, 7).is_empty());
+	}
+	// Too few values since the last missing day.
+	let mut values = exponential_series(20).values().to_vec();
+	values[17] = None;
+	let data = Series::new(date(1), values);
+	assert!(forecast(&data, ForecastModel::Growth, 14, 7).is_empty());
+	assert!(forecast(&data, ForecastModel::Exponential, 14, 7).is_empty());
+    }
+
+}
@@ -1,507 +1,1076 @@
+//! Vega-Lite graph writers. Every graph is written as a standalone
+//! HTML page under `output.path/group`.
+
 use std::{io,fs};
 use std::fs::File;
 use std::io::Write;
-use std::path::Path;
+use std::path::{Path,PathBuf};
+use std::cell::RefCell;
 use std::collections::HashMap;
 
+use chrono::{Local,Duration};
 use chrono::naive::NaiveDate;
 use serde_json::{Value,json};
+use unidecode::unidecode;
 
-use super::error::Result;
+use super::error::{Result,Error};
+use super::series::{Series,Tests,Estimate,GrowthMethod,Kernel};
+use super::{svg,export,index};
+use super::vega::{self,Spec,Mark,Channel,Opacity,Selection};
 
 
-pub type Series = Vec<(NaiveDate,f64)>;
+/// Series by region.
 pub type CasesData = Vec<(String,Series)>;
-pub type TestsData = Vec<(NaiveDate,(f64,f64,f64))>;
-pub type Population = HashMap<&'static str,u64>;
+/// Estimates by region.
+pub type EstimateData = Vec<(String,Series<Estimate>)>;
+/// Flagged dates by region.
+pub type Anomalies = Vec<(String,Vec<NaiveDate>)>;
+/// Daily test results.
+pub type TestsData = Series<Tests>;
+/// Population by region.
+pub type Population = HashMap<String,u64>;
+/// Horizontal reference lines, with optional labels.
 pub type Refs = Vec<(Option<&'static str>, f64)>;
+/// Regions of a map: name, NIS codes of the municipalities filled
+/// with the values of the region, and values.
+pub type MapData = Vec<(String,Vec<String>,Series)>;
+/// Weekly values by row: the Monday of each ISO week and the value.
+pub type WeeklyData = Vec<(String,Vec<(NaiveDate,f64)>)>;
+/// Values by age group and sex.
+pub type PyramidData = Vec<(String,String,Series)>;
+
+/// Where and how the graph pages are written.
+#[derive(Clone,Debug)]
+pub struct Output {
+    /// Root directory of the graphs.
+    pub path: PathBuf,
+    pub scripts: Scripts,
+    /// Static images written next to every page.
+    pub images: Vec<Image>,
+    /// Data files with the plotted values written next to every page.
+    pub exports: Vec<Export>,
+    /// The pages written so far.
+    written: RefCell<Vec<index::Entry>>,
+}
+
+/// How the pages load the Vega, Vega-Lite and Vega-Embed scripts.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Scripts {
+    /// From the jsDelivr CDN.
+    Cdn,
+    /// Embedded in every page.
+    Inline,
+    /// From the `assets` directory under the graph directory.
+    Local,
+}
+
+/// Format of the static images of the graphs.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Image {
+    Svg,
+    /// Rasterized SVG; requires the `png` feature.
+    Png,
+}
+
+/// Format of the data files with the plotted values of the graphs.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Export {
+    Csv,
+    Json,
+}
+
+/// The scripts bundled at build time from the `assets` directory,
+/// if they were found there.
+mod bundle {
+    include!(concat!(env!("OUT_DIR"), "/assets.rs"));
+}
+
+/// Days between the dates shown on the maps.
+const MAP_STEP: i64 = 7;
+
+/// Names and CDN locations of the scripts, in loading order.
+const SCRIPTS: [(&str,&str); 3] = [
+    ("vega.min.js", "https://cdn.jsdelivr.net/npm/vega@5"),
+    ("vega-lite.min.js", "https://cdn.jsdelivr.net/npm/vega-lite@4"),
+    ("vega-embed.min.js", "https://cdn.jsdelivr.net/npm/vega-embed"),
+];
+
+/// Optional layers added to a graph.
+#[derive(Clone,Debug,Default)]
+pub struct Overlays {
+    /// Intervals, shaded behind the lines.
+    pub bands: EstimateData,
+    /// Dates marked on the lines.
+    pub anomalies: Anomalies,
+    /// What the marked dates are, in their tooltip ("Possible data
+    /// dump" if None).
+    pub anomaly_note: Option<&'static str>,
+    /// Projections, drawn as dashed continuations of the lines with
+    /// their prediction intervals shaded.
+    pub forecast: EstimateData,
+}
+
+/// The boundaries of the Belgian municipalities for the maps: a
+/// TopoJSON topology with the municipalities as object
+/// `municipalities`, each with its NIS code and population as
+/// properties `nis` and `population`.
+#[derive(Clone,Debug)]
+pub struct Boundaries {
+    topology: Value,
+    /// Population by NIS code.
+    population: Population,
+}
+
 
-pub fn cases_graph(graph_path: &Path, group: &str, level: &str,
+/// `absolute.html`: cumulative counts (or active counts) by region.
+#[allow(clippy::too_many_arguments)]
+pub fn cases_graph(output: &Output, group: &str, level: &str,
 		   var: &str, scale: &Value, refs: &Refs,
-		   data: &CasesData) -> Result<()> {
-    let graph_path = graph_path.join(group);
-    graph(&graph_path, "absolute.html",
-	  &format!("Number of total {} by {}", var, level),
-	  "Count", scale, refs, data)
+		   data: &CasesData, overlays: &Overlays) -> Result<()> {
+    graph_overlays(output, group, "absolute.html",
+		   &format!("Number of total {} by {}", var, level),
+		   "Count", scale, refs, data, overlays)
 }
 
-pub fn relative_graph(graph_path: &Path, group: &str, level: &str,
+/// `relative.html`: cumulative counts per 100k by region.
+pub fn relative_graph(output: &Output, group: &str, level: &str,
 		      var: &str, scale: &Value, refs: &Refs,
 		      data: &CasesData) -> Result<()> {
-    let graph_path = graph_path.join(group);
-    graph(&graph_path, "relative.html",
+    graph(output, group, "relative.html",
 	  &format!("Number of total {} per 100k by {}", var, level),
 	  "Count / 100k", scale, refs, data)
 }
 
 
-pub fn daily_graph(graph_path: &Path, group: &str, level: &str, var: &str, refs: &Refs,
-		   smoothing: usize, data: &CasesData) -> Result<()> {
-    let graph_path = graph_path.join(group);
-    let filename = match smoothing {
-	1 => format!("daily.html"),
-	n => format!("daily-{}days.html", n),
-    };
+/// `daily[-Ndays[-kernel]].html`: (averaged) daily counts by region.
+#[allow(clippy::too_many_arguments)]
+pub fn daily_graph(output: &Output, group: &str, level: &str, var: &str, refs: &Refs,
+		   smoothing: usize, kernel: Kernel, data: &CasesData,
+		   overlays: &Overlays) -> Result<()> {
+    let filename = smoothed_filename("daily", smoothing, kernel);
     let title = match smoothing {
 	1 => format!("Number of daily {} by {}", var, level),
-	n => format!("{}-day average number of daily {} by {}",
-		     n, var, level),
+	n => format!("{}-day {}average number of daily {} by {}",
+		     n, kernel.adjective(), var, level),
     };
-    graph(&graph_path, &filename, &title, "Count",
-	  &json!({}), refs, data)
+    graph_overlays(output, group, &filename, &title, "Count",
+		   &json!({}), refs, data, overlays)
+}
+
+/// `daily-adjusted.html`: daily counts by region, adjusted for the
+/// day-of-week effect.
+pub fn daily_adjusted_graph(output: &Output, group: &str, level: &str, var: &str,
+			    refs: &Refs, data: &CasesData) -> Result<()> {
+    graph(output, group, "daily-adjusted.html",
+	  &format!("Number of daily {} by {}, adjusted for day of week", var, level),
+	  "Count", &json!({}), refs, data)
 }
 
-pub fn incidence_graph(graph_path: &Path, group: &str, level: &str, var: &str, refs: &Refs,
-		       smoothing: usize, data: &CasesData) -> Result<()> {
-    let graph_path = graph_path.join(group);
-    let filename = match smoothing {
-	1 => format!("incidence.html"),
-	n => format!("incidence-{}days.html", n),
+/// `incidence[-Ndays[-kernel]].html`: N-day incidence by region.
+#[allow(clippy::too_many_arguments)]
+pub fn incidence_graph(output: &Output, group: &str, level: &str, var: &str, refs: &Refs,
+		       smoothing: usize, kernel: Kernel, data: &CasesData,
+		       overlays: &Overlays) -> Result<()> {
+    let filename = smoothed_filename("incidence", smoothing, kernel);
+    let title = match smoothing {
+	1 => format!("1-day incidence of {} by {}", var, level),
+	n => format!("{}-day {}incidence of {} by {}",
+		     n, kernel.adjective(), var, level),
     };
+    graph_overlays(output, group, &filename, &title, "Incidence",
+		   &json!({}), refs, data, overlays)
+}
+
+
+/// `ratio[-Ndays[-kernel]].html`: ratio of the (averaged) daily
+/// counts of men to those of women.
+#[allow(clippy::too_many_arguments)]
+pub fn sex_ratio_graph(output: &Output, group: &str, level: &str, var: &str, refs: &Refs,
+		       smoothing: usize, kernel: Kernel, data: &CasesData) -> Result<()> {
+    let filename = smoothed_filename("ratio", smoothing, kernel);
     let title = match smoothing {
-	n => format!("{}-day incidence of {} by {}",
-		     n, var, level),
+	1 => format!("Ratio of male to female daily {} by {}", var, level),
+	n => format!("Ratio of male to female {}-day {}average daily {} by {}",
+		     n, kernel.adjective(), var, level),
     };
-    graph(&graph_path, &filename, &title, "Incidence",
-	  &json!({}), refs, data)
+    graph(output, group, &filename, &title, "Ratio", &json!({}), refs, data)
 }
 
 
-pub fn growth_graph(graph_path: &Path, group: &str, level: &str,
-		    var: &str, smoothing: usize, data: &CasesData) -> Result<()> {
-    let graph_path = graph_path.join(group);
-    let filename = match smoothing {
-	1 => format!("growth.html"),
-	n => format!("growth-{}days.html", n),
+/// `flow[-Ndays[-kernel]].html`: (averaged) daily admissions minus
+/// discharges by region.
+#[allow(clippy::too_many_arguments)]
+pub fn flow_graph(output: &Output, group: &str, level: &str, var: &str,
+		  smoothing: usize, kernel: Kernel, data: &CasesData) -> Result<()> {
+    let filename = smoothed_filename("flow", smoothing, kernel);
+    let title = match smoothing {
+	1 => format!("Net daily flow (admissions - discharges) of {} by {}", var, level),
+	n => format!("{}-day {}average net daily flow (admissions - discharges) of {} by {}",
+		     n, kernel.adjective(), var, level),
     };
+    graph(output, group, &filename, &title, "Count", &json!({}), &vec![(None, 0.0)], data)
+}
+
+/// `share[-Ndays[-kernel]].html`: (averaged) share of `var` among
+/// `of` by region.
+#[allow(clippy::too_many_arguments)]
+pub fn share_graph(output: &Output, group: &str, level: &str, var: &str, of: &str,
+		   smoothing: usize, kernel: Kernel, data: &CasesData) -> Result<()> {
+    let filename = smoothed_filename("share", smoothing, kernel);
     let title = match smoothing {
-	1 => format!("Daily growth of {} by {}", var, level),
-	n => format!("Average daily growth of {}-day average {} by {}",
-		     n, var, level)
+	1 => format!("Share of {} among {} by {}", var, of, level),
+	n => format!("{}-day {}average share of {} among {} by {}",
+		     n, kernel.adjective(), var, of, level),
     };
-    graph(&graph_path, &filename, &title, "Factor",
-	  &json!({"domain":[0.5, 1.5]}), &vec![(None, 1.0)], data)
+    graph(output, group, &filename, &title, "Share", &json!({"domain":[0.0, 1.0]}), &vec![], data)
 }
 
+/// `reporting.html`: number of hospitals reporting by region.
+pub fn reporting_graph(output: &Output, group: &str, level: &str, data: &CasesData,
+		       overlays: &Overlays) -> Result<()> {
+    graph_overlays(output, group, "reporting.html",
+		   &format!("Number of hospitals reporting by {}", level),
+		   "Hospitals", &json!({}), &vec![], data, overlays)
+}
 
-pub fn tests_graph(graph_path: &Path, group: &str, region: &str,
-		   smoothing: usize, data: &TestsData,
-		   refs: &Refs) -> Result<()> {
-    let graph_path = graph_path.join(group);
-    let filename = match smoothing {
-	1 => format!("tests.html"),
-	n => format!("tests-{}days.html", n),
+/// `per-hospital.html`: `var` per reporting hospital by region.
+pub fn per_hospital_graph(output: &Output, group: &str, level: &str, var: &str,
+			  data: &CasesData, overlays: &Overlays) -> Result<()> {
+    graph_overlays(output, group, "per-hospital.html",
+		   &format!("Number of {} per reporting hospital by {}", var, level),
+		   "Count / hospital", &json!({}), &vec![], data, overlays)
+}
+
+
+/// `growth[-Ndays[-kernel]].html` (ratio) or
+/// `growth-regression-Ndays.html`: daily growth factors by region,
+/// with the confidence band of the regression estimates.
+#[allow(clippy::too_many_arguments)]
+pub fn growth_graph(output: &Output, group: &str, level: &str,
+		    var: &str, smoothing: usize, kernel: Kernel, method: GrowthMethod,
+		    data: &EstimateData) -> Result<()> {
+    let filename = match method {
+	GrowthMethod::Ratio => smoothed_filename("growth", smoothing, kernel),
+	GrowthMethod::Regression => format!("growth-regression-{}days.html", smoothing),
+    };
+    let title = match (method, smoothing) {
+	(GrowthMethod::Ratio, 1) => format!("Daily growth of {} by {}", var, level),
+	(GrowthMethod::Ratio, n) => format!("Average daily growth of {}-day {}average {} by {}",
+					    n, kernel.adjective(), var, level),
+	(GrowthMethod::Regression, n) => format!("Daily growth of {} by {} \
+						  (Poisson regression over {} days)",
+						 var, level, n)
+    };
+    let bands = match method {
+	GrowthMethod::Ratio => vec![],
+	GrowthMethod::Regression => data.clone()
     };
+    graph_overlays(output, group, &filename, &title, "Factor",
+		   &json!({"domain":[0.5, 1.5]}), &vec![(None, 1.0)],
+		   &data.iter().map(
+		       |(region,series)| (region.clone(), series.map(|growth| growth.value))
+		   ).collect(), &Overlays { bands, ..Overlays::default() })
+}
+
+/// `doubling-Ndays[-kernel].html`: doubling (positive) or halving
+/// (negative) time by region, capped at `cap` days.
+#[allow(clippy::too_many_arguments)]
+pub fn doubling_graph(output: &Output, group: &str, level: &str, var: &str,
+		      smoothing: usize, kernel: Kernel, cap: f64, data: &CasesData) -> Result<()> {
+    let filename = smoothed_filename("doubling", smoothing, kernel);
+    let title = format!("Doubling (+) or halving (-) time of {}-day {}average {} by {}",
+			smoothing, kernel.adjective(), var, level);
+    graph(output, group, &filename, &title, "Days",
+	  &json!({"domain":[-cap, cap]}), &vec![(None, 0.0)], data)
+}
+
+/// `rt-Ndays.html`: effective reproduction number over an N-day
+/// window by region, with its credible interval.
+pub fn rt_graph(output: &Output, group: &str, level: &str,
+		var: &str, window: usize, data: &EstimateData) -> Result<()> {
+    let filename = format!("rt-{}days.html", window);
+    let title = format!("Effective reproduction number ({}-day window) of {} by {}",
+			window, var, level);
+    graph_overlays(output, group, &filename, &title, "Rt",
+		   &json!({"domain":[0.0, 3.0]}), &vec![(None, 1.0)],
+		   &data.iter().map(
+		       |(region,series)| (region.clone(), series.map(|rt| rt.value))
+		   ).collect(), &Overlays { bands: data.clone(), ..Overlays::default() })
+}
+
+
+/// `tests[-Ndays[-kernel]].html`: positivity and test count for a
+/// single region.
+pub fn tests_graph(output: &Output, group: &str, region: &str,
+		   smoothing: usize, kernel: Kernel, data: &TestsData,
+		   refs: &Refs) -> Result<()> {
+    let filename = smoothed_filename("tests", smoothing, kernel);
     let title = match smoothing {
 	1 => format!("Evolution of COVID-19 test results ({})", region),
-	n => format!("{}-day averaged evolution of COVID-19 \
-		      test results ({})", n, region)
+	n => format!("{}-day {}averaged evolution of COVID-19 \
+		      test results ({})", n, kernel.adjective(), region)
     };
-    graph_tests(&graph_path, &filename, &title, data, refs)
+    graph_tests(output, group, &filename, &title, data, refs)
 }
 
-pub fn test_positivity_graph(graph_path: &Path, group: &str, level: &str,
-			     smoothing: usize, data: &Vec<(String,TestsData)>,
+/// `positive-tests[-Ndays[-kernel]].html`: positivity ratio by region.
+pub fn test_positivity_graph(output: &Output, group: &str, level: &str,
+			     smoothing: usize, kernel: Kernel, data: &[(String,TestsData)],
 			     refs: &Refs) -> Result<()> {
-    let graph_path = graph_path.join(group);
-    let filename = match smoothing {
-	1 => format!("positive-tests.html"),
-	n => format!("positive-tests-{}days.html", n),
-    };
+    let filename = smoothed_filename("positive-tests", smoothing, kernel);
     let title = match smoothing {
 	1 => format!("Evolution of COVID-19 test positivity ratio by {}", level),
-	n => format!("{}-day averaged evolution of COVID-19 \
-		      test positivity ratio by {}", n, level)
+	n => format!("{}-day {}averaged evolution of COVID-19 \
+		      test positivity ratio by {}", n, kernel.adjective(), level)
     };
-    graph(&graph_path, &filename, &title, "Proportion of positive tests",
+    graph(output, group, &filename, &title, "Proportion of positive tests",
 	  &json!({"domain":[0.0, 1.0]}), refs, &data.iter().map(
-	      |(region,series)| (region.clone(), series.iter().map(
-		  |(date,(pos,neg,_all))| (date.clone(), pos / (pos + neg))
-	      ).collect())
+	      |(region,series)| (region.clone(), series.map(|tests| tests.positivity()))
 	  ).collect())
 }
 
-pub fn total_tests_graph(graph_path: &Path, group: &str, level: &str,
-			 smoothing: usize, data: &Vec<(String,TestsData)>) -> Result<()> {
-    let graph_path = graph_path.join(group);
-    let filename = match smoothing {
-	1 => format!("total-tests.html"),
-	n => format!("total-tests-{}days.html", n),
-    };
+/// `total-tests[-Ndays[-kernel]].html`: number of tests by region.
+pub fn total_tests_graph(output: &Output, group: &str, level: &str,
+			 smoothing: usize, kernel: Kernel,
+			 data: &[(String,TestsData)]) -> Result<()> {
+    let filename = smoothed_filename("total-tests", smoothing, kernel);
     let title = match smoothing {
 	1 => format!("Evolution of COVID-19 test count by {}", level),
-	n => format!("{}-day averaged evolution of COVID-19 \
-		      test count by {}", n, level)
+	n => format!("{}-day {}averaged evolution of COVID-19 \
+		      test count by {}", n, kernel.adjective(), level)
     };
-    graph(&graph_path, &filename, &title, "Number of tests",
+    graph(output, group, &filename, &title, "Number of tests",
 	  &json!({}), &vec![], &data.iter().map(
-	      |(region,series)| (region.clone(), series.iter().map(
-		  |(date,(_pos,_neg,all))| (date.clone(), *all)
-	      ).collect())
+	      |(region,series)| (region.clone(), series.map(|tests| tests.total))
 	  ).collect())
 }
 
 
-fn graph(graph_path: &Path, path: &str, title: &str, ytitle: &str,
-	 scale: &Value, refs: &Refs, data: &CasesData) -> Result<()> {
+/// `LEVEL.html`: map of the municipalities filled with the
+/// `window`-day incidence of their region at `level`, by week, with
+/// a slider to select the week and the `refs` as color thresholds.
+/// The static image shows the last week.
+#[allow(clippy::too_many_arguments)]
+pub fn map_graph(output: &Output, group: &str, level: &str, var: &str, refs: &Refs,
+		 window: usize, boundaries: &Boundaries, data: &MapData) -> Result<()> {
 
-    fs::create_dir_all(graph_path)?;
-    let mut out = io::BufWriter::new(File::create(graph_path.join(path))?);
+    let path = format!("{}.html", level);
+    let title = format!("{}-day incidence of {} by {}", window, var, level);
+
+    let dates = steps(data.iter().map(|(_,_,series)| series))?;
+
+    let values = dates.iter().enumerate().flat_map(|(week,date)| data.iter().flat_map(
+	move |(region,nis,series)| series.get(*date).filter(|val| val.is_finite()).into_iter().flat_map(
+	    move |val| nis.iter().map(move |nis| json!({
+		"Week": week,
+		"Date": format!("{}", date.format("%Y-%m-%d")),
+		"NIS": nis,
+		"Name": region,
+		"Incidence": val
+	    }))
+	))
+    ).collect();
+
+    let mut thresholds = refs.iter().map(|(_,r)| *r).collect::<Vec<_>>();
+    thresholds.sort_by(|a,b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let scale = json!({
+	"type": "threshold",
+	"domain": thresholds,
+	"scheme": {"name": "yelloworangered", "count": thresholds.len() + 1}
+    });
+
+    let spec = Spec::chart(&title)
+	.projection("mercator")
+	.layer_with(Spec::layer()
+		    .topojson(&boundaries.topology, "municipalities")
+		    .mark(Mark::geoshape().color("#eeeeee").stroke("white", 0.5)))
+	.layer_with(Spec::layer()
+		    .data(values)
+		    .filter("Week")
+		    .lookup_topojson("NIS", &boundaries.topology, "municipalities", "nis", "geo")
+		    .mark(Mark::geoshape().stroke("white", 0.5))
+		    .shape(Channel::geojson("geo"))
+		    .color(Channel::quantitative("Incidence").title("Incidence").scale(&scale))
+		    .tooltip(vec![
+			Channel::nominal("Name"),
+			Channel::nominal("Date"),
+			Channel::quantitative("Incidence").format(".1f")
+		    ]))
+	.layer_with(vega::slider_label("Week", dates.iter().map(
+	    |date| format!("{}", date.format("%Y-%m-%d"))
+	).collect()));
+
+    let last = dates.last().copied();
+    write_page(output, group, &path, &title, &spec, true, || svg::map(
+	&title, &thresholds, &boundaries.topology, "municipalities", &data.iter().flat_map(
+	    |(_,nis,series)| nis.iter().filter_map(
+		move |nis| Some((nis.as_str(), *series.get(last?)?))
+	    )
+	).collect()
+    ))?;
+
+    if !output.exports.is_empty() {
+	export::map(&output.path.join(group), level, &title, &dates, data, refs, &output.exports)?;
+    }
+
+    Ok(())
+
+}
+
+
+/// `heatmap[-region].html`: weekly incidence by age group and ISO
+/// week, in Belgium or in `region`.
+pub fn age_heatmap_graph(output: &Output, group: &str, region: Option<&str>, var: &str,
+			 data: &WeeklyData) -> Result<()> {
+    let (path,title) = match region {
+	None => ("heatmap.html".to_string(),
+		 format!("Weekly incidence of {} by age group", var)),
+	Some(region) => (format!("heatmap-{}.html", unidecode(region).to_lowercase()),
+			 format!("Weekly incidence of {} by age group in {}", var, region))
+    };
+    heatmap(output, group, &path, &title, "Age group", "Incidence", data)
+}
+
+
+/// `pyramid.html`: N-day incidence by age group and sex, as
+/// a population pyramid with a slider over the weeks. The static
+/// image shows the last week.
+pub fn pyramid_graph(output: &Output, group: &str, var: &str, window: usize,
+		     data: &PyramidData) -> Result<()> {
+
+    let path = "pyramid.html";
+    let title = format!("{}-day incidence of {} by age group and sex", window, var);
+
+    let dates = steps(data.iter().map(|(_,_,series)| series))?;
+    let max = data.iter().flat_map(|(_,_,series)| dates.iter().filter_map(move |date| series.get(*date)))
+	.filter(|val| val.is_finite()).fold(0.0, |max, val| f64::max(max, *val));
+
+    // The first sex (alphabetically, i.e. F) extends to the left.
+    let left = data.iter().map(|(_,sex,_)| sex).min();
+    let values = dates.iter().enumerate().flat_map(|(week,date)| data.iter().filter_map(
+	move |(age,sex,series)| series.get(*date).filter(|val| val.is_finite()).map(
+	    |val| json!({
+		"Week": week,
+		"Date": format!("{}", date.format("%Y-%m-%d")),
+		"Age": age,
+		"Sex": sex,
+		"Incidence": val,
+		"Bar": match Some(sex) == left { true => -val, false => *val }
+	    }))
+    )).collect();
+
+    let spec = Spec::chart(&title)
+	.layer_with(Spec::layer()
+		    .data(values)
+		    .filter("Week")
+		    .mark(Mark::bar())
+		    .y(Channel::ordinal("Age").title("Age group").sort("descending"))
+		    .x(Channel::quantitative("Bar").title("Incidence")
+		       .scale(&json!({"domain": [-max, max]})).abs_labels())
+		    .color(Channel::nominal("Sex"))
+		    .tooltip(vec![
+			Channel::ordinal("Age").title("Age group"),
+			Channel::nominal("Sex"),
+			Channel::nominal("Date"),
+			Channel::quantitative("Incidence").format(".1f")
+		    ]))
+	.layer_with(vega::slider_label("Week", dates.iter().map(
+	    |date| format!("{}", date.format("%Y-%m-%d"))
+	).collect()));
+
+    write_page(output, group, path, &title, &spec, true, || svg::pyramid(
+	&title, &data.iter().filter_map(
+	    |(age,sex,series)| Some((age.as_str(), sex.as_str(), *series.get(*dates.last()?)?))
+	).collect::<Vec<_>>()
+    ))?;
+
+    if !output.exports.is_empty() {
+	export::pyramid(&output.path.join(group), path.trim_end_matches(".html"), &title,
+			&dates, data, &output.exports)?;
+    }
+
+    Ok(())
+
+}
+
+
+impl Scripts {
+
+    pub const ALL: [Scripts; 3] = [
+	Self::Cdn,
+	Self::Inline,
+	Self::Local,
+    ];
+
+    pub fn name(&self) -> &'static str {
+	match self {
+	    Self::Cdn => "cdn",
+	    Self::Inline => "inline",
+	    Self::Local => "local"
+	}
+    }
+
+}
+
+impl Image {
+
+    pub const ALL: [Image; 2] = [
+	Self::Svg,
+	Self::Png,
+    ];
+
+    pub fn name(&self) -> &'static str {
+	match self {
+	    Self::Svg => "svg",
+	    Self::Png => "png"
+	}
+    }
+
+}
 
+impl Export {
+
+    pub const ALL: [Export; 2] = [
+	Self::Csv,
+	Self::Json,
+    ];
+
+    pub fn name(&self) -> &'static str {
+	match self {
+	    Self::Csv => "csv",
+	    Self::Json => "json"
+	}
+    }
+
+}
+
+impl Boundaries {
+
+    /// The boundaries bundled at build time from
+    /// `assets/municipalities.topojson`, if any.
+    pub fn bundled() -> Result<Option<Self>> {
+	bundle::BOUNDARIES.map(Self::parse).transpose()
+    }
+
+    pub fn parse(topojson: &str) -> Result<Self> {
+	let mut topology: Value = serde_json::from_str(topojson)?;
+	let geometries = topology.pointer_mut("/objects/municipalities/geometries")
+	    .and_then(Value::as_array_mut)
+	    .ok_or_else(|| Error::InvalidBoundaries("no municipalities object".to_string()))?;
+	let mut population = HashMap::new();
+	for geometry in geometries {
+	    let properties = geometry.get_mut("properties").and_then(Value::as_object_mut)
+		.ok_or_else(|| Error::InvalidBoundaries("municipality without properties".to_string()))?;
+	    // NIS codes are looked up as strings, as in the Sciensano data.
+	    let nis = match properties.get("nis") {
+		Some(Value::String(nis)) => nis.clone(),
+		Some(Value::Number(nis)) => nis.to_string(),
+		_ => return Err(Error::InvalidBoundaries("municipality without NIS code".to_string()))
+	    };
+	    if let Some(n) = properties.get("population").and_then(Value::as_u64) {
+		population.insert(nis.clone(), n);
+	    }
+	    properties.insert("nis".to_string(), Value::String(nis));
+	}
+	Ok(Self { topology, population })
+    }
+
+    /// Population by NIS code, of the municipalities that have one.
+    pub fn population(&self) -> &Population {
+	&self.population
+    }
+
+}
+
+impl Output {
+
+    pub fn new(path: PathBuf, scripts: Scripts, images: Vec<Image>, exports: Vec<Export>) -> Self {
+	Self { path, scripts, images, exports, written: RefCell::new(vec![]) }
+    }
+
+    /// The pages written so far.
+    pub fn written(&self) -> Vec<index::Entry> {
+	self.written.borrow().clone()
+    }
+
+    /// Add the page `path` in `group` to the pages written, with its
+    /// static images if they were written.
+    fn record(&self, group: &str, path: &str, title: &str, images: bool) {
+	let stem = format!("{}/{}", group, path.trim_end_matches(".html"));
+	self.written.borrow_mut().push(index::Entry {
+	    path: format!("{}/{}", group, path),
+	    title: title.to_string(),
+	    updated: format!("{}", Local::now().format("%Y-%m-%d %H:%M")),
+	    images: match images {
+		true => self.images.iter().map(|image| format!("{}.{}", stem, image.name())).collect(),
+		false => vec![]
+	    }
+	});
+    }
+
+    /// Prepare the graph directory: with local scripts, copy the
+    /// bundled scripts to `assets`. Fails if the scripts are to be
+    /// inlined but were not bundled, or if PNG images are requested
+    /// without the `png` feature.
+    pub fn prepare(&self) -> Result<()> {
+	if self.images.contains(&Image::Png) && !cfg!(feature = "png") {
+	    return Err(Error::InvalidArgument("PNG images require the png feature".to_string()));
+	}
+	let assets = self.path.join("assets");
+	match (self.scripts, bundle::SCRIPTS) {
+	    (Scripts::Inline, None) => Err(Error::InvalidArgument(
+		"the Vega scripts were not bundled into this build (see assets/README.md)".to_string()
+	    )),
+	    (Scripts::Local, Some(scripts)) => {
+		fs::create_dir_all(&assets)?;
+		for (name,script) in &scripts {
+		    fs::write(assets.join(name), script)?;
+		}
+		Ok(())
+	    },
+	    (Scripts::Local, None) => {
+		for (name,_) in &SCRIPTS {
+		    if !assets.join(name).is_file() {
+			println!("Warning: {} is missing from {}", name, assets.display());
+		    }
+		}
+		Ok(())
+	    },
+	    _ => Ok(())
+	}
+    }
+
+}
+
+
+/// The head of a page in `group`, loading the Vega scripts.
+fn write_head(out: &mut impl Write, output: &Output, group: &str, title: &str) -> Result<()> {
     write!(out, "<!DOCTYPE html><html><head>")?;
     write!(out, "<meta charset=\"UTF-8\">")?;
     write!(out, "<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">")?;
     write!(out, "<title>{}</title>", title)?;
-    write!(out, "<script src=\"https://cdn.jsdelivr.net/npm/vega@5\"></script>")?;
-    write!(out, "<script src=\"https://cdn.jsdelivr.net/npm/vega-lite@4\"></script>")?;
-    write!(out, "<script src=\"https://cdn.jsdelivr.net/npm/vega-embed\"></script>")?;
+    match (output.scripts, bundle::SCRIPTS) {
+	(Scripts::Inline, Some(scripts)) => for (_,script) in &scripts {
+	    write!(out, "<script>{}</script>", script.replace("</script", "<\\/script"))?;
+	},
+	(Scripts::Local, _) => for (name,_) in &SCRIPTS {
+	    write!(out, "<script src=\"{}assets/{}\"></script>",
+		   "../".repeat(Path::new(group).components().count()), name)?;
+	},
+	_ => for (_,url) in &SCRIPTS {
+	    write!(out, "<script src=\"{}\"></script>", url)?;
+	}
+    }
     write!(out, "</head>")?;
-    write!(out, "<body>")?;
-    write!(out, "<div id=\"vis\" style=\"overflow: hidden; position: absolute;top: 0; left: 0; right: 0; bottom: 0;\"></div>")?;
-    write!(out, "<script type=\"text/javascript\">")?;
-    write!(out, "var spec = ")?;
+    Ok(())
+}
+
+
+/// `base.html` without smoothing, else `base-Ndays.html` with the
+/// name of the kernel appended unless it is the default trailing one.
+fn smoothed_filename(base: &str, smoothing: usize, kernel: Kernel) -> String {
+    match (smoothing, kernel) {
+	(1, _) => format!("{}.html", base),
+	(n, Kernel::Trailing) => format!("{}-{}days.html", base, n),
+	(n, kernel) => format!("{}-{}days-{}.html", base, n, kernel.name())
+    }
+}
+
+
+#[allow(clippy::too_many_arguments)]
+fn graph(output: &Output, group: &str, path: &str, title: &str, ytitle: &str,
+	 scale: &Value, refs: &Refs, data: &CasesData) -> Result<()> {
+    graph_overlays(output, group, path, title, ytitle, scale, refs, data, &Overlays::default())
+}
+
+
+/// Like `graph`, with `overlays` drawn over or behind the lines.
+#[allow(clippy::too_many_arguments)]
+fn graph_overlays(output: &Output, group: &str, path: &str, title: &str, ytitle: &str,
+		  scale: &Value, refs: &Refs, data: &CasesData,
+		  overlays: &Overlays) -> Result<()> {
 
-    serde_json::to_writer_pretty(out.by_ref(), &json!({
-	"$schema": "https://vega.github.io/schema/vega-lite/v4.json",
-	"height": "container",
-	"width": "container",
-	"title": title,
-	"data": {
-	    "values": data.iter().flat_map(
-		|(region,vals)| vals.iter().filter_map(
-		    move |(date,val)| match val.is_finite() {
+    write_page(output, group, path, title, &lines_spec(title, ytitle, scale, refs, data, overlays),
+	       false, || svg::lines(title, ytitle, scale, refs, data, overlays))?;
+
+    if !output.exports.is_empty() {
+	export::lines(&output.path.join(group), path.trim_end_matches(".html"), title, ytitle, refs,
+		      data, overlays, &output.exports)?;
+    }
+
+    Ok(())
+
+}
+
+
+/// The spec of `graph_overlays`.
+fn lines_spec(title: &str, ytitle: &str, scale: &Value, refs: &Refs, data: &CasesData,
+	      overlays: &Overlays) -> Spec {
+
+    let date = |date: NaiveDate| format!("{}", date.format("%Y-%m-%d"));
+    let region = || Channel::nominal("Region");
+    let y = |field: &str| Channel::quantitative(field).scale(scale);
+
+    let mut spec = Spec::chart(title).data(data.iter().flat_map(
+	|(region,vals)| vals.present().filter_map(
+	    move |(d,val)| match val.is_finite() {
+		false => None,
+		true => Some(json!({
+		    "Date": date(d),
+		    "Region": region.to_string(),
+		    "Value": val
+		}))
+	    })
+    ).collect());
+
+    if !overlays.bands.is_empty() {
+	spec = spec.layer_with(vega::band(y("Lower"), "Upper", 0.2)
+	    .data(overlays.bands.iter().flat_map(
+		|(region,vals)| vals.present().filter_map(
+		    move |(d,val)| match val.lower.is_finite() && val.upper.is_finite() {
 			false => None,
 			true => Some(json!({
-			    "Date": format!("{}", date.format("%Y-%m-%d")),
+			    "Date": date(d),
 			    "Region": region.to_string(),
-			    "Value": val
+			    "Lower": val.lower,
+			    "Upper": val.upper
 			}))
 		    })
-	    ).collect::<Vec<_>>()
-	},
-	"layer": [
-	    {
-		"encoding": {
-		    "color": {
-			"field": "Region",
-			"type":"nominal"
-		    },
-		    "x": {
-			"field":"Date",
-			//"scale": {"domain": ["2020-03-31","2020-07-18"]},
-			"timeUnit": "utcyearmonthdate",
-			"title":"Date",
-			"type":"temporal"
-		    },
-		    "y": {
-			"field":"Value",
-			"title": ytitle,
-			"scale": scale,
-			"type":"quantitative"
-		    }
-		},
-		"layer": [
-		    {
-			"mark":"line",
-			"selection": {
-			    "Highlight": {"bind":"legend","type":"multi","fields":["Region"]},
-			    "Grid": {"bind":"scales","type":"interval"}
-			},
-			"encoding":{
-			    "opacity":{"value":0.1,"condition":{"value":1,"selection":"Highlight"}}
-			}
-		    },
-		    {
-			"mark":"point",
-			"encoding": {
-			    "opacity": {
-				"value":0,
-				"condition": [
-				    {"value":1,"test":{"and":[{"selection":"Highlight"},{"selection":"Hover"}]}},
-				    {"value":0.2,"selection":"Hover"}
-				]
-			    }
-			}
-		    }
-		]	
-	    },
-	    {
-		"transform": [
-		    {
-			"groupby": ["Date"],
-			"value": "Value",
-			"pivot": "Region"
-		    }
-		],
-		"mark": {
-		    "color": "gray",
-		    "tooltip": {"content":"data"},
-		    "type": "rule"
-		},
-		"selection": {
-		    "Hover": {
-			"nearest":true,
-			"empty":"none",
-			"clear":"mouseout",
-			"type":"single",
-			"on":"mouseover",
-			"fields":["Date"]
-		    }
-		},
-		"encoding": {
-		    "opacity": {
-			"value": 0,
-			"condition": {
-			    "value": 1,
-			    "selection": "Hover"
-			}
-		    },
-		    "x": {
-			"field":"Date",
-			"type":"temporal"
-		    },
-		    "tooltip": vec![
-			json!({"field":"Date","type":"temporal"})
-		    ].into_iter().chain(data.iter().map(
-			|(region,_)| json!({"field":region,"format":".3f","type":"quantitative"})
-		    )).collect::<Vec<_>>()
-		}
-	    },
-	    {
-		"data": {
-		    "values": refs.iter().map(
-			|(name,y)| json!({
-			    "Name": name.unwrap_or(""),
-			    "Value": y
-			})).collect::<Vec<_>>()
-		},
-		"encoding": {
-		    "y": {
-			"field":"Value",
-			"type":"quantitative"
-		    }
-		},
-		"layer": [
-		    {
-			"mark": {
-			    "color": "red",
-			    "opacity": 0.5,
-			    "size": 1,
-			    "type":"rule"
-			}
-		    },
-		    {
-			"mark": {
-			    "type": "text",
-			    "color": "red"
-			},
-			"encoding": {
-			    "text": {"field": "Name"}
-			}
-		    }
-		]
-	    }
-	]
-    }))?;
+	    ).collect())
+	    .color(region())
+	    .x(Channel::date("Date")));
+    }
 
-    write!(out, ";vegaEmbed('#vis', spec,{{}}).then(function(result) {{")?;
-    write!(out, "}}).catch(console.error);")?;
-    write!(out, "</script>")?;
-    write!(out, "</body></html>")?;
+    spec = spec
+	.layer_with(vega::highlighted_lines(region(), Channel::date("Date").title("Date"),
+					    y("Value").title(ytitle)))
+	.layer_with(vega::hover_rule("Date", vec![
+	    Channel::temporal("Date")
+	].into_iter().chain(data.iter().map(
+	    |(region,_)| Channel::quantitative(region).format(".3f")
+	)).collect())
+		    .pivot("Region", "Value", "Date")
+		    .mark(Mark::rule().color("gray").data_tooltip())
+		    .x(Channel::temporal("Date")))
+	.layer_with(vega::reference_rules(refs, Channel::quantitative("Value"), None));
 
-    Ok(())
+    if !overlays.forecast.is_empty() {
+	spec = spec.layer_with(Spec::layer()
+	    .data(overlays.forecast.iter().flat_map(
+		|(region,vals)| vals.present().filter_map(
+		    move |(d,val)| match val.value.is_finite() && val.lower.is_finite() && val.upper.is_finite() {
+			false => None,
+			true => Some(json!({
+			    "Date": date(d),
+			    "Region": region.to_string(),
+			    "Value": val.value,
+			    "Lower": val.lower,
+			    "Upper": val.upper
+			}))
+		    })
+	    ).collect())
+	    .color(region())
+	    .x(Channel::date("Date"))
+	    .layer_with(vega::band(y("Lower"), "Upper", 0.15))
+	    .layer_with(Spec::layer()
+			.mark(Mark::line().dashed(4.0))
+			.y(y("Value"))
+			.opacity(Opacity::highlight(1.0, 0.1))));
+    }
+
+    if !overlays.anomalies.is_empty() {
+	spec = spec.layer_with(Spec::layer()
+	    .data(overlays.anomalies.iter().flat_map(|(region,dates)| {
+		let vals = data.iter().find(|(r,_)| r == region).map(|(_,vals)| vals);
+		dates.iter().filter_map(
+		    move |d| vals?.get(*d).filter(|val| val.is_finite()).map(
+			|val| json!({
+			    "Date": date(*d),
+			    "Region": region.to_string(),
+			    "Value": val,
+			    "Note": overlays.anomaly_note.unwrap_or("Possible data dump")
+			})
+		    )
+		)
+	    }).collect())
+	    .mark(Mark::point().shape("diamond").size(60.0).data_tooltip())
+	    .color(region())
+	    .x(Channel::date("Date"))
+	    .y(y("Value"))
+	    .opacity(Opacity::highlight(1.0, 0.1)));
+    }
+
+    spec
 
 }
 
 
-fn graph_tests(graph_path: &Path, path: &str, title: &str,
-	       data: &TestsData, refs: &Refs) -> Result<()> {
+/// The dates of a chart with a slider over `series`: every
+/// `MAP_STEP` days back from the last date.
+fn steps<'a>(series: impl Iterator<Item = &'a Series> + Clone) -> Result<Vec<NaiveDate>> {
+    let first = series.clone().filter_map(|series| series.present().next().map(|(date,_)| date)).min()
+	.ok_or(Error::MissingData)?;
+    let last = series.filter_map(|series| series.last().map(|(date,_)| date)).max()
+	.ok_or(Error::MissingData)?;
+    let mut dates = (0..).map(|i| last - Duration::days(i * MAP_STEP))
+	.take_while(|date| *date >= first).collect::<Vec<_>>();
+    dates.reverse();
+    Ok(dates)
+}
+
+
+/// Write the page `path` in `group` showing `spec`, with its static
+/// image drawn by `image` if images are requested (charts that cannot
+/// be drawn return None), and add it to the pages written. Pages with
+/// a `slider` (see `vega::slider_label`) leave room for it below the
+/// chart, and are written compactly since they tend to be large.
+fn write_page(output: &Output, group: &str, path: &str, title: &str, spec: &Spec,
+	      slider: bool, image: impl FnOnce() -> Option<String>) -> Result<()> {
 
-    fs::create_dir_all(graph_path)?;
+    let graph_path = output.path.join(group);
+    fs::create_dir_all(&graph_path)?;
     let mut out = io::BufWriter::new(File::create(graph_path.join(path))?);
 
-    write!(out, "<!DOCTYPE html><html><head>")?; 
-    write!(out, "<meta charset=\"UTF-8\">")?;
-    write!(out, "<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">")?;
-    write!(out, "<title>{}</title>", title)?;
-    write!(out, "<script src=\"https://cdn.jsdelivr.net/npm/vega@5\"></script>")?;
-    write!(out, "<script src=\"https://cdn.jsdelivr.net/npm/vega-lite@4\"></script>")?;
-    write!(out, "<script src=\"https://cdn.jsdelivr.net/npm/vega-embed\"></script>")?;
-    write!(out, "</head>")?;
+    write_head(&mut out, output, group, title)?;
     write!(out, "<body>")?;
-    write!(out, "<div id=\"vis\" style=\"overflow: hidden; position: absolute;top: 0; left: 0; right: 0; bottom: 0;\"></div>")?;
+    write!(out, "<div id=\"vis\" style=\"overflow: hidden; position: absolute;top: 0; left: 0; right: 0; bottom: {};\"></div>",
+	   match slider { true => "2em", false => "0" })?;
     write!(out, "<script type=\"text/javascript\">")?;
     write!(out, "var spec = ")?;
-
-    serde_json::to_writer_pretty(out.by_ref(), &json!({
-	"height": "container",
-	"width": "container",
-	"$schema": "https://vega.github.io/schema/vega-lite/v4.json",
-	"title": title,
-	"layer": [
-	    {
-		"data": {
-		    "values": data.iter().filter_map(
-			|(date,(pos,neg,all))| match *pos + *neg == 0.0 {
-			    true => None,
-			    false => Some(json!({
-				"Date": format!("{}", date.format("%Y-%m-%d")),
-				"Total": all,
-				"Positive": 1f64.min(pos / (pos + neg))
-			    }))
-			}
-		    ).collect::<Vec<_>>(),
-		},
-		"encoding": {
-		    "x": {
-			"field": "Date",
-			"timeUnit": "utcyearmonthdate",
-			"title": "Date",
-			"type": "temporal"
-		    }
-		},
-		"resolve": {
-		    "scale": {
-			"y": "independent"
-		    }
-		},
-		"layer": [
-		    {
-			"mark": {
-			    "color": "red",
-			    "type": "line"
-			},
-			"selection": {
-			    "Grid1": {"bind":"scales","type":"interval"}
-			},
-			"encoding": {
-			    "y": {
-				"field": "Positive",
-				"scale": {
-				    "domain": [
-					0,
-					1
-				    ],
-				    "type": "linear"
-				},
-				"type": "quantitative",
-				"axis": {
-				    "titleColor": "red",
-				    "title": "Proportion of positive tests"
-				}
-			    }
-			}
-		    },
-		    {
-			"mark": {
-			    "color": "blue",
-			    "type": "line"
-			},
-			"selection": {
-			    "Grid2": {"bind":"scales","type":"interval"}
-			},
-			"encoding": {
-			    "y": {
-				"field": "Total",
-				"scale": {
-				    "type": "linear",
-				    "domainMin": 0
-				},
-				"type": "quantitative",
-				"axis": {
-				    "titleColor": "blue",
-				    "title": "Total number of tests"
-				}
-			    }
-			}
-		    },
-		    {
-			"mark": {
-			    "color": "gray",
-			    "type": "rule"
-			},
-			"selection": {
-			    "Hover": {
-				"nearest":true,
-				"empty":"none",
-				"clear":"mouseout",
-				"type":"single",
-				"on":"mouseover",
-				"fields":["Date"]
-			    }
-			},
-			"encoding": {
-			    "opacity": {
-				"value": 0,
-				"condition": {
-				    "value": 1,
-				    "selection": "Hover"
-				}
-			    },
-			    "tooltip": [
-				{"field": "Date", "type": "temporal"},
-				{"field": "Total", "type": "quantitative",
-				 "format": ".0f"},
-				{"field": "Positive", "type": "quantitative",
-				 "format": ".3f"}
-			    ]
-			}
-		    }
-		]
-	    },
-	    {
-		"data": {
-		    "values": refs.iter().map(
-			|(name,y)| json!({
-			    "Name": name.unwrap_or(""),
-			    "Value": y
-			})).collect::<Vec<_>>()
-		},
-		"encoding": {
-		    "y": {
-			"field":"Value",
-			"type":"quantitative",
-			"scale": {
-			    "domain": [0, 1]
-			},
-			"axis": {
-			    "title": null
-			}
-		    }
-		},
-		"layer": [
-		    {
-			"mark": {
-			    "color": "red",
-			    "opacity": 0.5,
-			    "size": 1,
-			    "type":"rule"
-			},
-			"selection": {
-			    "Grid3": {"bind":"scales","type":"interval"}
-			}
-		    },
-		    {
-			"mark": {
-			    "type": "text",
-			    "color": "red"
-			},
-			"encoding": {
-			    "text": {
-				"field": "Name"
-			    }
-			}
-		    }
-		]
-	    }
-	]
-    }))?;
-
+    match slider {
+	true => serde_json::to_writer(out.by_ref(), spec)?,
+	false => serde_json::to_writer_pretty(out.by_ref(), spec)?
+    }
     write!(out, ";vegaEmbed('#vis', spec,{{}}).then(function(result) {{")?;
     write!(out, "}}).catch(console.error);")?;
     write!(out, "</script>")?;
     write!(out, "</body></html>")?;
 
+    let image = match output.images.is_empty() {
+	true => None,
+	false => image()
+    };
+    if let Some(image) = &image {
+	svg::write(&graph_path, path.trim_end_matches(".html"), image, &output.images)?;
+    }
+    output.record(group, path, title, image.is_some());
+
+    Ok(())
+
+}
+
+
+/// A heatmap of `data`, with the ISO weeks on the x axis, the rows
+/// on the y axis (titled `ytitle`) and the values (`value`) as
+/// colors.
+fn heatmap(output: &Output, group: &str, path: &str, title: &str, ytitle: &str,
+	   value: &str, data: &WeeklyData) -> Result<()> {
+
+    let spec = Spec::chart(title)
+	.data(data.iter().flat_map(|(row,weeks)| weeks.iter().filter(|(_,val)| val.is_finite()).map(
+	    move |(monday,val)| json!({
+		"Week": format!("{}", monday.format("%G-W%V")),
+		"Monday": format!("{}", monday.format("%Y-%m-%d")),
+		"Row": row,
+		"Value": val
+	    })
+	)).collect())
+	.mark(Mark::rect())
+	.x(Channel::ordinal("Week").title("Week"))
+	.y(Channel::ordinal("Row").title(ytitle).sort("descending"))
+	.color(Channel::quantitative("Value").title(value)
+	       .scale(&json!({"scheme": "yelloworangered"})))
+	.tooltip(vec![
+	    Channel::ordinal("Week"),
+	    Channel::nominal("Monday"),
+	    Channel::ordinal("Row").title(ytitle),
+	    Channel::quantitative("Value").title(value).format(".1f")
+	]);
+
+    write_page(output, group, path, title, &spec, false,
+	       || svg::heatmap(title, ytitle, value, data))?;
+
+    if !output.exports.is_empty() {
+	export::weekly(&output.path.join(group), path.trim_end_matches(".html"), title, value, data, &output.exports)?;
+    }
+
+    Ok(())
+
+}
+
+
+fn graph_tests(output: &Output, group: &str, path: &str, title: &str,
+	       data: &TestsData, refs: &Refs) -> Result<()> {
+
+    write_page(output, group, path, title, &tests_spec(title, data, refs), false,
+	       || svg::tests(title, data, refs))?;
+
+    if !output.exports.is_empty() {
+	export::tests(&output.path.join(group), path.trim_end_matches(".html"), title, data, refs, &output.exports)?;
+    }
+
     Ok(())
 
 }
+
+
+/// The spec of `graph_tests`.
+fn tests_spec(title: &str, data: &TestsData, refs: &Refs) -> Spec {
+
+    let values = data.present().filter_map(
+	|(date,tests)| match tests.positive + tests.negative == 0.0 {
+	    true => None,
+	    false => Some(json!({
+		"Date": format!("{}", date.format("%Y-%m-%d")),
+		"Total": tests.total,
+		"Positive": 1f64.min(tests.positivity())
+	    }))
+	}
+    ).collect();
+
+    let positivity = Spec::layer()
+	.mark(Mark::line().color("red"))
+	.select("Grid1", Selection::scales())
+	.y(Channel::quantitative("Positive")
+	   .scale(&json!({"domain": [0, 1], "type": "linear"}))
+	   .axis("Proportion of positive tests", "red"));
+
+    let totals = Spec::layer()
+	.mark(Mark::line().color("blue"))
+	.select("Grid2", Selection::scales())
+	.y(Channel::quantitative("Total")
+	   .scale(&json!({"type": "linear", "domainMin": 0}))
+	   .axis("Total number of tests", "blue"));
+
+    Spec::chart(title)
+	.layer_with(vega::dual_axis(positivity, totals)
+		    .data(values)
+		    .x(Channel::date("Date").title("Date"))
+		    .layer_with(vega::hover_rule("Date", vec![
+			Channel::temporal("Date"),
+			Channel::quantitative("Total").format(".0f"),
+			Channel::quantitative("Positive").format(".3f")
+		    ])))
+	.layer_with(vega::reference_rules(
+	    refs,
+	    Channel::quantitative("Value").scale(&json!({"domain": [0, 1]})).untitled_axis(),
+	    Some("Grid3")))
+
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn date(day: u32) -> NaiveDate {
+	NaiveDate::from_ymd(2020, 10, day)
+    }
+
+    fn to_json(spec: &Spec) -> Value {
+	serde_json::to_value(spec).unwrap()
+    }
+
+    fn cases() -> CasesData {
+	vec![
+	    ("Brussels".to_string(), Series::new(date(1), vec![Some(1.0), None, Some(3.0), Some(f64::NAN)])),
+	    ("Flanders".to_string(), Series::from_values(date(2), vec![2.0, 4.0, 8.0]))
+	]
+    }
+
+    fn estimates(start: NaiveDate) -> EstimateData {
+	vec![("Flanders".to_string(), Series::new(start, vec![
+	    Some(Estimate { value: 8.0, lower: 6.0, upper: 10.0 }),
+	    None,
+	    Some(Estimate { value: 16.0, lower: f64::NAN, upper: 20.0 }),
+	    Some(Estimate { value: 32.0, lower: 24.0, upper: 40.0 })
+	]))]
+    }
+
+    fn refs() -> Refs {
+	vec![(Some("Threshold"), 0.5), (None, 2.0)]
+    }
+
+    /// The number of data values of `spec`.
+    fn values(spec: &Value) -> usize {
+	spec["data"]["values"].as_array().map_or(0, Vec::len)
+    }
+
+    #[test]
+    fn lines() {
+	let scale = json!({"type": "log"});
+	let data = cases();
+
+	let spec = to_json(&lines_spec("Title", "Cases", &scale, &refs(), &data, &Overlays::default()));
+	assert_eq!(spec["title"], "Title");
+	assert_eq!(values(&spec), 5);
+	let layers = spec["layer"].as_array().unwrap();
+	assert_eq!(layers.len(), 3);
+	assert_eq!(layers[0]["encoding"]["y"]["title"], "Cases");
+	assert_eq!(layers[0]["encoding"]["y"]["scale"], scale);
+	assert_eq!(layers[1]["encoding"]["tooltip"], json!([
+	    {"field": "Date", "type": "temporal"},
+	    {"field": "Brussels", "type": "quantitative", "format": ".3f"},
+	    {"field": "Flanders", "type": "quantitative", "format": ".3f"}
+	]));
+	assert_eq!(layers[2]["data"]["values"], json!([
+	    {"Name": "Threshold", "Value": 0.5},
+	    {"Name": "", "Value": 2.0}
+	]));
+
+	let overlays = Overlays {
+	    bands: estimates(date(2)),
+	    anomalies: vec![
+		("Flanders".to_string(), vec![date(3), date(10)]),
+		("Brussels".to_string(), vec![date(1), date(4)])
+	    ],
+	    anomaly_note: None,
+	    forecast: estimates(date(5))
+	};
+	let spec = to_json(&lines_spec("Title", "Cases", &scale, &vec![], &data, &overlays));
+	let layers = spec["layer"].as_array().unwrap();
+	assert_eq!(layers.len(), 6);
+	// The bands behind the lines, the forecast and anomalies over them.
+	assert_eq!(values(&layers[0]), 2);
+	assert_eq!(layers[0]["encoding"]["y"]["scale"], scale);
+	assert_eq!(layers[3]["data"]["values"], json!([]));
+	assert_eq!(values(&layers[4]), 2);
+	assert_eq!(layers[4]["layer"][1]["encoding"]["y"]["field"], "Value");
+	assert_eq!(layers[5]["data"]["values"], json!([
+	    {"Date": "2020-10-03", "Region": "Flanders", "Value": 4.0, "Note": "Possible data dump"},
+	    {"Date": "2020-10-01", "Region": "Brussels", "Value": 1.0, "Note": "Possible data dump"}
+	]));
+
+	let spec = to_json(&lines_spec("Title", "Cases", &scale, &vec![], &data, &Overlays {
+	    anomalies: overlays.anomalies.clone(),
+	    anomaly_note: Some("Few reporting hospitals"),
+	    ..Overlays::default()
+	}));
+	assert_eq!(spec["layer"].as_array().unwrap().len(), 4);
+	assert_eq!(spec["layer"][3]["data"]["values"][0]["Note"], "Few reporting hospitals");
+    }
+
+    #[test]
+    fn tests() {
+	let tests = |positive, negative| Tests { positive, negative, total: positive + negative };
+	let data = Series::new(date(1), vec![
+	    Some(tests(10.0, 90.0)),
+	    Some(tests(0.0, 0.0)),
+	    None,
+	    Some(tests(30.0, 20.0))
+	]);
+	let spec = to_json(&tests_spec("Tests", &data, &refs()));
+	let layers = spec["layer"].as_array().unwrap();
+	assert_eq!(layers.len(), 2);
+	assert_eq!(layers[0]["data"]["values"], json!([
+	    {"Date": "2020-10-01", "Total": 100.0, "Positive": 0.1},
+	    {"Date": "2020-10-04", "Total": 50.0, "Positive": 0.6}
+	]));
+	assert_eq!(layers[0]["layer"][2]["encoding"]["tooltip"][1]["field"], "Total");
+	assert_eq!(values(&layers[1]), 2);
+	assert_eq!(layers[1]["encoding"]["y"]["scale"], json!({"domain": [0, 1]}));
+	assert!(layers[1]["layer"][0]["selection"]["Grid3"].is_object());
+    }
+
+}
@@ -0,0 +1,188 @@
+//! Navigation pages over the written graphs: an `index.html` in every
+//! directory and a landing page at the root, built from the manifest
+//! of all graphs written so far.
+
+use std::io;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::collections::{BTreeMap,BTreeSet};
+
+use serde::{Serialize,Deserialize};
+
+use super::error::Result;
+
+
+/// A graph page written during a run.
+#[derive(Serialize,Deserialize,Clone,Debug)]
+pub struct Entry {
+    /// Path of the page relative to the graph directory.
+    pub path: String,
+    pub title: String,
+    /// Local time the page was written.
+    pub updated: String,
+    /// Static images of the graph, relative to the graph directory.
+    pub images: Vec<String>,
+}
+
+impl Entry {
+
+    /// The directory of the page, relative to the graph directory.
+    fn group(&self) -> &str {
+	self.path.rfind('/').map_or("", |i| &self.path[..i])
+    }
+
+    fn file(&self) -> &str {
+	self.path.rfind('/').map_or(&self.path, |i| &self.path[i+1..])
+    }
+
+    /// The metric and smoothing of the page, from its file name
+    /// (`metric[-Ndays[-kernel]].html`).
+    fn metric(&self) -> (String, Option<(usize,Option<String>)>) {
+	let stem = self.file().trim_end_matches(".html");
+	let parts = stem.split('-').collect::<Vec<_>>();
+	match parts.iter().position(|part| part.ends_with("days") && part.trim_end_matches("days")
+				    .parse::<usize>().is_ok()) {
+	    Some(i) if i > 0 => (parts[..i].join("-"), Some((
+		parts[i].trim_end_matches("days").parse().unwrap_or(1),
+		match parts.len() > i + 1 {
+		    true => Some(parts[i+1..].join("-")),
+		    false => None
+		}
+	    ))),
+	    _ => (stem.to_string(), None)
+	}
+    }
+
+}
+
+
+/// Merge the `written` entries into the manifest in `graph_path`,
+/// dropping the pages that no longer exist, and rewrite the index
+/// pages.
+pub fn update(graph_path: &Path, written: &[Entry]) -> Result<()> {
+
+    let manifest_path = graph_path.join("manifest.json");
+    let mut entries = match File::open(&manifest_path) {
+	Ok(file) => serde_json::from_reader::<_,Vec<Entry>>(io::BufReader::new(file))?,
+	Err(err) if err.kind() == io::ErrorKind::NotFound => vec![],
+	Err(err) => return Err(err.into())
+    }.into_iter().map(|entry| (entry.path.clone(), entry)).collect::<BTreeMap<_,_>>();
+
+    for entry in written {
+	entries.insert(entry.path.clone(), entry.clone());
+    }
+    let entries = entries.into_iter().map(|(_,entry)| entry)
+	.filter(|entry| graph_path.join(&entry.path).is_file())
+	.collect::<Vec<_>>();
+    serde_json::to_writer_pretty(io::BufWriter::new(File::create(&manifest_path)?), &entries)?;
+
+    let mut dirs = BTreeSet::new();
+    for entry in &entries {
+	let mut dir = entry.group();
+	dirs.insert(dir);
+	while let Some(i) = dir.rfind('/') {
+	    dir = &dir[..i];
+	    dirs.insert(dir);
+	}
+	dirs.insert("");
+    }
+
+    for dir in dirs {
+	write_index(graph_path, dir, &entries)?;
+    }
+
+    Ok(())
+
+}
+
+
+/// `dir/index.html`: the subdirectories of `dir`, the graphs of every
+/// group under it by metric, and the graphs in `dir` itself with their
+/// thumbnails.
+fn write_index(graph_path: &Path, dir: &str, entries: &[Entry]) -> Result<()> {
+
+    let prefix = match dir { "" => String::new(), dir => format!("{}/", dir) };
+    let below = entries.iter().filter(|entry| entry.path.starts_with(&prefix)).collect::<Vec<_>>();
+    let relative = |path: &str| path[prefix.len()..].to_string();
+    let title = match dir { "" => "COVID-19 graphs".to_string(), dir => format!("COVID-19 graphs: {}", dir) };
+
+    let subdirs = below.iter().filter_map(|entry| {
+	let rest = &entry.path[prefix.len()..];
+	rest.find('/').map(|i| &rest[..i])
+    }).collect::<BTreeSet<_>>();
+
+    let mut groups = BTreeMap::new();
+    for entry in &below {
+	groups.entry(entry.group()).or_insert_with(BTreeMap::new)
+	    .entry(entry.metric().0).or_insert_with(Vec::new).push(*entry);
+    }
+
+    let mut out = io::BufWriter::new(File::create(graph_path.join(dir).join("index.html"))?);
+
+    write!(out, "<!DOCTYPE html><html><head>")?;
+    write!(out, "<meta charset=\"UTF-8\">")?;
+    write!(out, "<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">")?;
+    write!(out, "<title>{}</title>", title)?;
+    write!(out, "<style>body {{ font-family: sans-serif; }} \
+		 td {{ padding: 0.1em 0.6em; vertical-align: top; }} \
+		 .graphs {{ display: flex; flex-wrap: wrap; }} \
+		 .graph {{ width: 300px; margin: 0.5em; }} \
+		 .graph img {{ width: 300px; border: 1px solid #ddd; }}</style>")?;
+    write!(out, "</head>")?;
+    write!(out, "<body>")?;
+    write!(out, "<h1>{}</h1>", title)?;
+
+    if !dir.is_empty() {
+	write!(out, "<p><a href=\"../index.html\">Up</a></p>")?;
+    }
+
+    if !subdirs.is_empty() {
+	write!(out, "<p>")?;
+	for subdir in &subdirs {
+	    write!(out, "<a href=\"{0}/index.html\">{0}</a> ", subdir)?;
+	}
+	write!(out, "</p>")?;
+    }
+
+    for (group,metrics) in &groups {
+	let link = match relative(&format!("{}/", group)).as_str() {
+	    "" => "index.html".to_string(),
+	    path => format!("{}index.html", path)
+	};
+	write!(out, "<h2><a href=\"{}\">{}</a></h2><table>", link, group)?;
+	for (metric,pages) in metrics {
+	    write!(out, "<tr><td>{}</td><td>", metric)?;
+	    for page in pages {
+		let label = match page.metric().1 {
+		    None if pages.len() == 1 => "view".to_string(),
+		    None => "1 day".to_string(),
+		    Some((n, None)) => format!("{} days", n),
+		    Some((n, Some(kernel))) => format!("{} days {}", n, kernel)
+		};
+		write!(out, "<a href=\"{}\" title=\"{}\">{}</a> ", relative(&page.path), page.title, label)?;
+	    }
+	    write!(out, "</td><td>{}</td></tr>",
+		   pages.iter().map(|page| page.updated.as_str()).max().unwrap_or(""))?;
+	}
+	write!(out, "</table>")?;
+    }
+
+    let here = below.iter().filter(|entry| entry.group() == dir).collect::<Vec<_>>();
+    if !here.is_empty() {
+	write!(out, "<h2>Graphs</h2><div class=\"graphs\">")?;
+	for entry in here {
+	    write!(out, "<div class=\"graph\"><a href=\"{}\">", entry.file())?;
+	    if let Some(image) = entry.images.first() {
+		write!(out, "<img src=\"{}\" alt=\"\"><br>", relative(image))?;
+	    }
+	    write!(out, "{}</a><br><small>{}</small></div>", entry.title, entry.updated)?;
+	}
+	write!(out, "</div>")?;
+    }
+
+    write!(out, "</body></html>")?;
+
+    Ok(())
+
+}
@@ -0,0 +1,60 @@
+//! Loaders for COVID-19 datasets (JHU CSSE, Sciensano, SUS), the
+//! transformations used on the resulting time series, Rt estimation,
+//! forecasts and Vega-Lite graph writers.
+//!
+//! ```no_run
+//! use std::path::Path;
+//! use covid19_growth_rs::{sciensano,series,cache::CacheMode};
+//!
+//! let data = sciensano::cases_muni(Path::new("cache"), CacheMode::Normal)?;
+//! let leuven = series::interpolate(&sciensano::cases_muni_series(
+//!     &data, |cs| sciensano::Level::Municipality.filter_muni("Leuven", cs)));
+//! let weekly = series::sum(&series::daily(&leuven), 7);
+//! # Ok::<(), covid19_growth_rs::error::Error>(())
+//! ```
+
+pub mod error;
+pub mod cache;
+pub mod config;
+pub mod graph;
+pub mod vega;
+pub mod svg;
+pub mod export;
+pub mod index;
+pub mod series;
+pub mod rt;
+pub mod forecast;
+pub mod report;
+pub mod alert;
+pub mod csse;
+pub mod sciensano;
+pub mod sus;
+pub mod pipeline;
+
+use chrono::naive::NaiveDate;
+
+
+/// Iterator over consecutive days, from a start date up to and
+/// including an optional end date.
+#[derive(Clone,Debug)]
+pub struct NaiveDateRange(NaiveDate,Option<NaiveDate>);
+
+impl NaiveDateRange {
+    pub fn new(start: NaiveDate, end: Option<NaiveDate>) -> Self {
+	Self(start, end)
+    }
+}
+
+impl Iterator for NaiveDateRange {
+    type Item = NaiveDate;
+    fn next(&mut self) -> Option<NaiveDate> {
+	match self.1.map_or(true, |end| self.0 <= end) {
+	    false => None,
+	    true => {
+		let current = self.0;
+		self.0 = self.0.succ();
+		Some(current)
+	    }
+	}
+    }
+}
@@ -0,0 +1,1055 @@
+//! The graph pipelines run by the binary: load a source, transform
+//! its series and write the graphs.
+
+use std::path::Path;
+use std::collections::{BTreeMap,HashMap};
+
+use chrono::{Datelike,Duration};
+use chrono::naive::NaiveDate;
+use serde_json::json;
+use unidecode::unidecode;
+
+use super::{csse,sciensano,sus,graph,rt,forecast,report,alert,NaiveDateRange};
+use super::graph::{Output,Boundaries,MapData,WeeklyData,CasesData,EstimateData,TestsData,Population,Refs,Anomalies,Overlays};
+use super::series::*;
+use super::error::{Result,Error};
+use super::config::{CsseRegion,SusConfig};
+use super::cache::CacheMode;
+use super::rt::SerialInterval;
+use super::forecast::ForecastModel;
+use super::alert::AlertConfig;
+
+
+/// Number of recent weeks to estimate the day-of-week effect from.
+const WEEKDAY_WEEKS: usize = 6;
+
+/// Days are anomalous when they exceed the median of the centered
+/// `ANOMALY_WINDOW` days by more than `ANOMALY_THRESHOLD` robust
+/// z-scores.
+const ANOMALY_WINDOW: usize = 15;
+const ANOMALY_THRESHOLD: f64 = 3.5;
+
+/// Forecasts are fitted on the last `FORECAST_WINDOW` days and
+/// extend `FORECAST_HORIZON` days beyond the data.
+const FORECAST_WINDOW: usize = 14;
+const FORECAST_HORIZON: usize = 14;
+
+/// Doubling and halving times are capped at this many days.
+const MAX_DOUBLING_TIME: f64 = 100.0;
+
+/// Days are flagged when fewer hospitals reported than
+/// `REPORTING_DROP` of the median of the centered `REPORTING_WINDOW`
+/// days.
+const REPORTING_WINDOW: usize = 15;
+const REPORTING_DROP: f64 = 0.9;
+
+/// Test positivity is compared to its reference levels over this
+/// many days.
+const POSITIVITY_WINDOW: usize = 7;
+
+
+/// Parameters of the transformations and estimates, shared by all
+/// pipelines.
+#[derive(Clone,Debug)]
+pub struct Settings {
+    /// Smoothing windows, in days.
+    pub smoothings: Vec<usize>,
+    /// Spread corrections of cumulative case counts over this many
+    /// preceding days.
+    pub redistribute: Option<usize>,
+    /// Replace anomalous days by the median of the surrounding days
+    /// before smoothing.
+    pub exclude_anomalies: bool,
+    /// Model for the projections of the daily counts and the active
+    /// counts, if any.
+    pub forecast: Option<ForecastModel>,
+    /// Weighting of the days in the smoothing windows.
+    pub kernel: Kernel,
+    /// Also write daily counts adjusted for the day-of-week effect.
+    pub weekday_adjustment: bool,
+    /// Growth estimators, each with its own growth graphs.
+    pub growth_methods: Vec<GrowthMethod>,
+    /// Serial interval distribution for the Rt estimates.
+    pub serial_interval: SerialInterval,
+    /// Alerts on reference level crossings, if enabled.
+    pub alerts: Option<AlertConfig>,
+}
+
+
+/// Case graphs for the CSSE groups in the configuration.
+pub fn csse_graphs(output: &Output, cache_path: &Path, mode: CacheMode,
+		   settings: &Settings, groups: &BTreeMap<String,Vec<CsseRegion>>) -> Result<()> {
+
+    let data = csse::confirmed(cache_path, mode)?;
+
+    for (group,regions) in groups {
+
+	let mut regions = regions.iter().collect::<Vec<_>>();
+	regions.sort_by(|a,b| a.name.cmp(&b.name));
+
+	case_graphs(output, settings,
+		    &format!("csse/{}", group), "country", "confirmed COVID-19 cases",
+		    &regions.iter().map(
+			|region| Ok((region.name.clone(), sum_series(&region.keys.iter().map(
+			    |key| data.get(key).ok_or_else(|| Error::MissingRegion(key.clone()))
+			).collect::<Result<Vec<_>>>()?).ok_or_else(|| Error::MissingRegion(region.name.clone()))?))
+		    ).collect::<Result<_>>()?,
+		    &regions.iter().map(
+			|region| (region.name.clone(), region.population)
+		    ).collect(), &vec![])?;
+
+    }
+
+    Ok(())
+
+}
+
+
+/// Case graphs for the configured Belgian municipalities, and maps
+/// of all municipalities.
+pub fn sciensano_muni_graphs(output: &Output, cache_path: &Path, mode: CacheMode,
+			     settings: &Settings,
+			     belgium: &BTreeMap<sciensano::Level,Population>) -> Result<()> {
+
+    let refs = vec![
+	(Some("Niveau 2"),  20.0 / 14.0),
+	(Some("Niveau 3"), 120.0 / 14.0),
+	(Some("Niveau 4"), 400.0 / 14.0)
+    ];
+    
+    let data = sciensano::cases_muni(cache_path, mode)?;
+
+    for (level,regions) in belgium {
+
+	let mut names = regions.keys().collect::<Vec<_>>();
+	names.sort();
+
+	let cases = names.iter().map(|region| {
+	    let series = sciensano::cases_muni_series(&data, |cs| level.filter_muni(region, cs));
+	    (region.to_string(), interpolate(&series))
+	}).collect();
+
+	case_graphs(output, settings,
+		    &format!("belgium/cases/{}", level.name()), level.name(),
+		    "confirmed COVID-19 cases", &cases, regions, &refs)?;
+	level_report(output, settings, &format!("belgium/cases/{}", level.name()), level.name(),
+		     "confirmed COVID-19 cases", &cases, regions, &refs, 14)?;
+
+    }
+
+    muni_maps(output, &data, &refs)
+
+}
+
+
+/// Maps of the 14-day incidence of all municipalities, and of their
+/// provinces and regions, if the municipality boundaries were bundled.
+/// The population of the municipalities is taken from the boundaries.
+/// Levels without any region to show are skipped.
+fn muni_maps(output: &Output, data: &[Vec<sciensano::CasesMuni>], refs: &Refs) -> Result<()> {
+
+    let boundaries = match Boundaries::bundled()? {
+	Some(boundaries) => boundaries,
+	None => {
+	    println!("Warning: no municipality boundaries bundled (see assets/README.md), skipping maps");
+	    return Ok(());
+	}
+    };
+
+    let cases = sciensano::cases_muni_by_nis(data).into_iter().map(
+	|(nis,series)| (nis, interpolate(&series))
+    ).collect::<BTreeMap<_,_>>();
+    if cases.is_empty() {
+	println!("Warning: no NIS codes in the municipality data (refresh the cache with fetch), skipping maps");
+	return Ok(());
+    }
+
+    let window = 14;
+    let refs = refs.iter().map(|(n,r)| (*n, *r * window as f64)).collect();
+
+    for level in &[sciensano::Level::Municipality, sciensano::Level::Province, sciensano::Level::Region] {
+
+	let (map, missing) = muni_map(*level, data, &cases, boundaries.population(), window);
+	if missing > 0 {
+	    println!("Warning: no population for {} {}(s), left out of the map", missing, level.name());
+	}
+	if map.is_empty() {
+	    println!("Warning: no {} with data, skipping its map", level.name());
+	    continue;
+	}
+
+	graph::map_graph(output, "belgium/cases/map", level.name(), "confirmed COVID-19 cases",
+			 &refs, window, &boundaries, &map)?;
+
+    }
+
+    Ok(())
+
+}
+
+
+/// The incidence over `window` days of the municipalities in `data`
+/// grouped by `level` (see `Level::region_of`), from their cases by
+/// NIS code. The population of a region is the sum of that of its
+/// municipalities in `population`, by NIS code; regions with a
+/// municipality without population are left out, and counted.
+fn muni_map(level: sciensano::Level, data: &[Vec<sciensano::CasesMuni>],
+	    cases: &BTreeMap<String,Series>, population: &Population,
+	    window: usize) -> (MapData, usize) {
+
+    // The latest record of each municipality, for its names.
+    let munis = data.iter().flatten().filter_map(
+	|cs| cs.nis5.as_ref().map(|nis| (nis.as_str(), cs))
+    ).collect::<BTreeMap<_,_>>();
+
+    let mut regions = BTreeMap::new();
+    for (nis,cs) in &munis {
+	let key = match level {
+	    sciensano::Level::Municipality => Some(nis.to_string()),
+	    level => level.region_of(cs)
+	};
+	if let (Some(key), Some(name)) = (key, level.region_of(cs)) {
+	    regions.entry(key).or_insert_with(|| (name, vec![])).1.push(nis.to_string());
+	}
+    }
+
+    let mut missing = 0;
+    let map = regions.into_iter().filter_map(|(_,(name,nis))| {
+	match nis.iter().map(|nis| population.get(nis)).sum::<Option<u64>>() {
+	    None => { missing += 1; None },
+	    Some(population) => {
+		let series = sum_series(&nis.iter().filter_map(|nis| cases.get(nis)).collect::<Vec<_>>())?;
+		Some((name, nis, sum(&incidence(&daily(&series), population), window)))
+	    }
+	}
+    }).collect();
+
+    (map, missing)
+
+}
+
+
+/// Case graphs by country, province, region and age group.
+pub fn sciensano_agesex_graphs(output: &Output, cache_path: &Path, mode: CacheMode,
+			       settings: &Settings,
+			       population: &HashMap<String,Population>) -> Result<()> {
+
+    let data = sciensano::cases_agesex(cache_path, mode)?;
+
+    let mut by_province = BTreeMap::new();
+    let mut by_region = BTreeMap::new();
+    let mut by_country = BTreeMap::new();
+    let mut by_agegroup = BTreeMap::new();
+    let mut by_province_agegroup = BTreeMap::new();
+    let mut by_sex = BTreeMap::new();
+    let mut by_sex_agegroup = BTreeMap::new();
+
+    let refs = vec![
+	(Some("Niveau 2"),  20.0 / 14.0),
+	(Some("Niveau 3"), 120.0 / 14.0),
+	(Some("Niveau 4"), 400.0 / 14.0)
+    ];
+    
+    for row in &data {
+	let date = NaiveDate::parse_from_str(row.date.as_deref()
+					     .unwrap_or("2020-02-29"), "%Y-%m-%d")?;
+	if let Some(province) = row.province.clone() {
+	    *by_province.entry(province).or_insert_with(BTreeMap::new)
+		.entry(date).or_insert(0.0) += row.cases as f64;
+	}
+	if let Some(region) = row.region.clone() {
+	    *by_region.entry(region).or_insert_with(BTreeMap::new)
+		.entry(date).or_insert(0.0) += row.cases as f64;
+	}
+	if let Some(agegroup) = row.agegroup.clone() {
+	    *by_agegroup.entry(agegroup).or_insert_with(BTreeMap::new)
+		.entry(date).or_insert(0.0) += row.cases as f64;
+	}
+	if let (Some(province), Some(agegroup)) = (row.province.clone(), row.agegroup.clone()) {
+	    *by_province_agegroup.entry(province).or_insert_with(BTreeMap::new)
+		.entry(agegroup).or_insert_with(BTreeMap::new)
+		.entry(date).or_insert(0.0) += row.cases as f64;
+	}
+	if let Some(sex) = row.sex.clone() {
+	    *by_sex.entry(sex.clone()).or_insert_with(BTreeMap::new)
+		.entry(date).or_insert(0.0) += row.cases as f64;
+	    if let Some(agegroup) = row.agegroup.clone() {
+		*by_sex_agegroup.entry(sex).or_insert_with(BTreeMap::new)
+		    .entry(agegroup).or_insert_with(BTreeMap::new)
+		    .entry(date).or_insert(0.0) += row.cases as f64;
+	    }
+	}
+	*by_country.entry(date).or_insert(0.0) += row.cases as f64;
+    }
+
+    let (start,end) = span(&by_country)?;
+
+    sex_graphs(output, settings, by_sex, by_sex_agegroup, population, &refs, start, end)?;
+
+    // There are no population figures by age and province; the
+    // provinces are assumed to have the age structure of the country.
+    let ages = population.get("age").ok_or_else(|| Error::MissingRegion("age".to_string()))?;
+    let country = *population.get("country").and_then(|population| population.get("Belgium"))
+	.ok_or_else(|| Error::MissingRegion("Belgium".to_string()))?;
+    graph::age_heatmap_graph(output, "belgium/cases/age", None, "confirmed COVID-19 cases",
+			     &weekly_incidence(&by_agegroup, ages, 1.0, start, end))?;
+    for (province,by_agegroup) in &by_province_agegroup {
+	let share = *population.get("province").and_then(|population| population.get(province))
+	    .ok_or_else(|| Error::MissingRegion(province.clone()))? as f64 / country as f64;
+	graph::age_heatmap_graph(output, "belgium/cases/age", Some(province), "confirmed COVID-19 cases",
+				 &weekly_incidence(by_agegroup, ages, share, start, end))?;
+    }
+
+    let groups = vec![
+	("country", vec![("Belgium".to_string(), Series::from_map(start, end, &by_country).fill(0.0))]),
+	("province", series_by_region(by_province, start, end)),
+	("region", series_by_region(by_region, start, end)),
+	("age", series_by_region(by_agegroup, start, end))
+    ];
+
+    for (group,regions) in groups {
+	//regions.sort();
+	let data = regions.iter().map(|(k,v)| (k.clone(), cumsum(v))).collect();
+	let population = population.get(group).ok_or_else(|| Error::MissingRegion(group.to_string()))?;
+	case_graphs(output, settings,
+		    &format!("belgium/cases/{}", group),
+		    group, "confirmed COVID-19 cases", &data, population, &refs)?;
+	level_report(output, settings, &format!("belgium/cases/{}", group),
+		     group, "confirmed COVID-19 cases", &data, population, &refs, 14)?;
+    }
+
+    Ok(())
+
+}
+
+
+/// Case graphs by sex, the ratio of male to female cases and the
+/// pyramid of the incidence by age group and sex, with the
+/// populations by sex ("sex") and by age group of each sex ("age-F",
+/// "age-M").
+#[allow(clippy::too_many_arguments)]
+fn sex_graphs(output: &Output, settings: &Settings,
+	      by_sex: BTreeMap<String,BTreeMap<NaiveDate,f64>>,
+	      by_sex_agegroup: BTreeMap<String,BTreeMap<String,BTreeMap<NaiveDate,f64>>>,
+	      population: &HashMap<String,Population>, refs: &Refs,
+	      start: NaiveDate, end: NaiveDate) -> Result<()> {
+
+    let group = "belgium/cases/sex";
+    let var = "confirmed COVID-19 cases";
+    let daily_data = series_by_region(by_sex, start, end);
+    let sexes = population.get("sex").ok_or_else(|| Error::MissingRegion("sex".to_string()))?;
+    if let Some((sex,_)) = daily_data.iter().find(|(sex,_)| !sexes.contains_key(sex)) {
+	return Err(Error::MissingRegion(sex.clone()));
+    }
+
+    let by = |name: &str| daily_data.iter().find(|(sex,_)| sex == name).map(|(_,series)| series);
+    if let (Some(male), Some(female)) = (by("M"), by("F")) {
+	let ratio_refs = vec![(None, 1.0), (Some("Population"), sexes["M"] as f64 / sexes["F"] as f64)];
+	for smoothing in &settings.smoothings {
+	    let ratio = smooth(male, *smoothing, settings.kernel).zip_with(
+		&smooth(female, *smoothing, settings.kernel), |male,female| male / female
+	    );
+	    graph::sex_ratio_graph(output, group, "sex", var, &ratio_refs, *smoothing, settings.kernel,
+				   &vec![("Belgium".to_string(), ratio)])?;
+	}
+    }
+
+    let data = daily_data.iter().map(|(k,v)| (k.clone(), cumsum(v))).collect();
+    case_graphs(output, settings, group, "sex", var, &data, sexes, refs)?;
+    level_report(output, settings, group, "sex", var, &data, sexes, refs, 14)?;
+
+    let window = 14;
+    let mut pyramid = vec![];
+    for (sex,by_agegroup) in by_sex_agegroup {
+	let ages_group = format!("age-{}", sex);
+	let ages = population.get(&ages_group).ok_or_else(|| Error::MissingRegion(ages_group.clone()))?;
+	for (age,days) in by_agegroup {
+	    let population = *ages.get(&age).ok_or_else(|| Error::MissingRegion(format!("{} {}", ages_group, age)))?;
+	    pyramid.push((
+		age, sex.clone(),
+		sum(&incidence(&Series::from_map(start, end, &days).fill(0.0), population), window)
+	    ));
+	}
+    }
+    graph::pyramid_graph(output, group, var, window, &pyramid)?;
+
+    Ok(())
+
+}
+
+
+/// Hospital admission, occupancy, ICU, respiratory support and
+/// ECMO graphs by country, province and region, with the net flow of
+/// patients, the share of them in ICU and the occupancy per reporting
+/// hospital.
+pub fn sciensano_hospitalization_graphs(output: &Output, cache_path: &Path, mode: CacheMode,
+					settings: &Settings,
+					population: &HashMap<String,Population>) -> Result<()> {
+
+    let data = sciensano::hospitalizations(cache_path, mode)?;
+
+    let refs = vec![
+	(Some("Niveau 3"),  3.5 / 7.0),
+	(Some("Niveau 4"), 14.0 / 7.0)
+    ];
+
+    let hosp_in_groups = hospitalization_groups(&data, |row| row.new_in)?;
+    let hosp_out_groups = hospitalization_groups(&data, |row| row.new_out)?;
+    let hosp_groups = hospitalization_groups(&data, |row| row.total_in)?;
+    let icu_groups = hospitalization_groups(&data, |row| row.total_in_icu)?;
+    let resp_groups = hospitalization_groups(&data, |row| row.total_in_resp)?;
+    let ecmo_groups = hospitalization_groups(&data, |row| row.total_in_ecmo)?;
+    let reporting_groups = hospitalization_groups(&data, |row| row.nr_reporting)?;
+
+    for (group,regions) in &hosp_in_groups {
+	let data = regions.iter().map(|(k,v)| (k.clone(), cumsum(v))).collect();
+	let population = population.get(*group).ok_or_else(|| Error::MissingRegion(group.to_string()))?;
+	case_graphs(output, settings,
+		    &format!("belgium/hospitalizations-in/{}", group),
+		    group, "COVID-19 hospitalizations in", &data, population, &refs)?;
+	level_report(output, settings, &format!("belgium/hospitalizations-in/{}", group),
+		     group, "COVID-19 hospitalizations in", &data, population, &refs, 7)?;
+    }
+
+    for (group,regions) in &hosp_groups {
+	active_graphs(output, settings,
+		      &format!("belgium/hospitalizations/{}", group),
+		      group, "COVID-19 hospitalizations net", regions,
+		      population.get(*group).ok_or_else(|| Error::MissingRegion(group.to_string()))?)?;
+    }
+
+    for (group,regions) in &icu_groups {
+	active_graphs(output, settings,
+		      &format!("belgium/hospitalizations-icu/{}", group),
+		      group, "COVID-19 patients in icu", regions,
+		      population.get(*group).ok_or_else(|| Error::MissingRegion(group.to_string()))?)?;
+    }
+
+    for (group,regions) in &resp_groups {
+	active_graphs(output, settings,
+		      &format!("belgium/hospitalizations-resp/{}", group),
+		      group, "COVID-19 patients on respiratory support", regions,
+		      population.get(*group).ok_or_else(|| Error::MissingRegion(group.to_string()))?)?;
+    }
+
+    for (group,regions) in &ecmo_groups {
+	active_graphs(output, settings,
+		      &format!("belgium/hospitalizations-ecmo/{}", group),
+		      group, "COVID-19 patients on ECMO", regions,
+		      population.get(*group).ok_or_else(|| Error::MissingRegion(group.to_string()))?)?;
+    }
+
+    for ((group,admissions),(_,discharges)) in hosp_in_groups.iter().zip(&hosp_out_groups) {
+	let flow = admissions.iter().zip(discharges).map(
+	    |((region,admissions),(_,discharges))| (region.clone(), admissions.zip_with(discharges, |a,d| a - d))
+	).collect::<CasesData>();
+	for smoothing in &settings.smoothings {
+	    graph::flow_graph(output, &format!("belgium/hospitalizations-flow/{}", group),
+			      group, "COVID-19 patients", *smoothing, settings.kernel, &flow.iter().map(
+				  |(region,series)| (region.clone(), smooth(series, *smoothing, settings.kernel))
+			      ).collect())?;
+	}
+    }
+
+    for ((group,icu),(_,hosp)) in icu_groups.iter().zip(&hosp_groups) {
+	for smoothing in &settings.smoothings {
+	    graph::share_graph(output, &format!("belgium/hospitalizations-icu/{}", group),
+			       group, "patients in ICU", "hospitalized COVID-19 patients",
+			       *smoothing, settings.kernel, &icu.iter().zip(hosp).map(
+				   |((region,icu),(_,hosp))| (region.clone(), smooth(icu, *smoothing, settings.kernel).zip_with(
+				       &smooth(hosp, *smoothing, settings.kernel), |icu,hosp| icu / hosp))
+			       ).collect())?;
+	}
+    }
+
+    for ((group,hosp),(_,reporting)) in hosp_groups.iter().zip(&reporting_groups) {
+	let overlays = Overlays {
+	    anomalies: reporting.iter().map(
+		|(region,series)| (region.clone(), drops(series, REPORTING_WINDOW, REPORTING_DROP))
+	    ).collect(),
+	    anomaly_note: Some("Fewer hospitals reporting"),
+	    ..Overlays::default()
+	};
+	let group_path = format!("belgium/hospitalizations/{}", group);
+	graph::reporting_graph(output, &group_path, group, reporting, &overlays)?;
+	graph::per_hospital_graph(output, &group_path, group, "hospitalized COVID-19 patients",
+				  &hosp.iter().zip(reporting).map(
+				      |((region,hosp),(_,reporting))| (region.clone(), hosp.zip_with(reporting, |h,r| h / r))
+				  ).collect(), &overlays)?;
+    }
+
+    Ok(())
+
+}
+
+
+/// The daily sums of `value` over the rows of `data`, by country,
+/// province and region.
+fn hospitalization_groups(data: &[sciensano::Hospitalizations],
+			  value: impl Fn(&sciensano::Hospitalizations) -> u64) -> Result<Vec<(&'static str,CasesData)>> {
+
+    let mut by_province = BTreeMap::new();
+    let mut by_region = BTreeMap::new();
+    let mut by_country = BTreeMap::new();
+
+    for row in data {
+
+	let date = NaiveDate::parse_from_str(row.date.as_deref()
+					     .unwrap_or("2020-02-29"), "%Y-%m-%d")?;
+
+	if let Some(province) = row.province.clone() {
+	    *by_province.entry(province).or_insert_with(BTreeMap::new)
+		.entry(date).or_insert(0.0) += value(row) as f64;
+	}
+
+	if let Some(region) = row.region.clone() {
+	    *by_region.entry(region).or_insert_with(BTreeMap::new)
+		.entry(date).or_insert(0.0) += value(row) as f64;
+	}
+
+	*by_country.entry(date).or_insert(0.0) += value(row) as f64;
+
+    }
+
+    let (start,end) = span(&by_country)?;
+
+    Ok(vec![
+	("country", vec![("Belgium".to_string(), Series::from_map(start, end, &by_country).fill(0.0))]),
+	("province", series_by_region(by_province, start, end)),
+	("region", series_by_region(by_region, start, end))
+    ])
+
+}
+
+/// Test graphs by country, province and region.
+pub fn sciensano_test_graphs(output: &Output, cache_path: &Path, mode: CacheMode,
+			     settings: &Settings) -> Result<()> {
+
+    let data = sciensano::tests(cache_path, mode)?;
+    let mut by_province = BTreeMap::new();
+    let mut by_region = BTreeMap::new();
+    let mut by_country = BTreeMap::new();
+
+    let refs = vec![
+	(Some("Niveau 3"), 0.03),
+	(Some("Niveau 4"), 0.06)
+    ];
+    
+    for row in &data {
+	let date = NaiveDate::parse_from_str(row.date.as_deref()
+					     .unwrap_or("2020-02-29"), "%Y-%m-%d")?;
+	let tests = Tests {
+	    positive: row.tests_all_pos as f64,
+	    negative: (row.tests_all - row.tests_all_pos) as f64,
+	    total: row.tests_all as f64
+	};
+	if let Some(province) = row.province.clone() {
+	    *by_province.entry(province).or_insert_with(BTreeMap::new)
+		.entry(date).or_default() += tests;
+	}
+	if let Some(region) = row.region.clone() {
+	    *by_region.entry(region).or_insert_with(BTreeMap::new)
+		.entry(date).or_default() += tests;
+	}
+	*by_country.entry(date).or_default() += tests;
+    }
+
+    let (start,end) = span(&by_country)?;
+
+    let groups = vec![
+	("region", series_by_region(by_region, start, end)),
+	("province", series_by_region(by_province, start, end))
+    ];
+
+    test_graphs(output, settings, "belgium/tests/country", "Belgium",
+		&Series::from_map(start, end, &by_country).fill(Tests::default()), &refs)?;
+
+    for (group,regions) in groups {
+	test_graphs_regions(output, settings,
+			    &format!("belgium/tests/{}", group),
+			    group, &regions, &refs)?;
+    }
+
+    Ok(())
+
+}
+
+/// Death graphs by country, region and age group. The age groups of
+/// the mortality data are wider than those of the cases and have
+/// their own population ("age-mort").
+pub fn sciensano_mortality_graphs(output: &Output, cache_path: &Path, mode: CacheMode,
+				  settings: &Settings,
+				  population: &HashMap<String,Population>) -> Result<()> {
+
+    let data = sciensano::mortality(cache_path, mode)?;
+
+    let mut by_region = BTreeMap::new();
+    let mut by_country = BTreeMap::new();
+    let mut by_agegroup = BTreeMap::new();
+
+    for row in &data {
+	let date = NaiveDate::parse_from_str(row.date.as_deref().unwrap_or("2020-02-29"),
+					     "%Y-%m-%d")?;
+	if let Some(region) = row.region.clone() {
+	    *by_region.entry(region).or_insert_with(BTreeMap::new)
+		.entry(date).or_insert(0.0) += row.deaths as f64;
+	}
+	if let Some(agegroup) = row.agegroup.clone() {
+	    *by_agegroup.entry(agegroup).or_insert_with(BTreeMap::new)
+		.entry(date).or_insert(0.0) += row.deaths as f64;
+	}
+	*by_country.entry(date).or_insert(0.0) += row.deaths as f64;
+    }
+
+    let (start,end) = span(&by_country)?;
+
+    let groups = vec![
+	("country", "country", vec![("Belgium".to_string(), Series::from_map(start, end, &by_country).fill(0.0))]),
+	("region", "region", series_by_region(by_region, start, end)),
+	("age", "age-mort", series_by_region(by_agegroup, start, end))
+    ];
+
+    for (group,population_group,regions) in groups {
+	let data = regions.iter().map(|(k,v)| (k.clone(), cumsum(v))).collect();
+	let population = population.get(population_group)
+	    .ok_or_else(|| Error::MissingRegion(population_group.to_string()))?;
+	case_graphs(output, settings,
+		    &format!("belgium/deaths/{}", group),
+		    group, "COVID-19 deaths", &data, population, &vec![])?;
+    }
+
+    Ok(())
+
+}
+
+
+/// Test data of the Brazilian states and of the municipalities, by
+/// name.
+pub type SusData = (Vec<(String,TestsData)>, Vec<(String,TestsData)>);
+
+/// Query (or load from cache) the test data for the configured
+/// Brazilian states and municipalities. Failed queries are reported
+/// and skipped.
+pub fn sus_data(cache_path: &Path, mode: CacheMode, sus: &SusConfig) -> SusData {
+
+    let data = sus.estados.iter().filter_map(
+	|estado| match sus::tests(cache_path, mode, sus::Level::Estado, &estado.nome,
+				 &estado.codigo.to_lowercase()) {
+	    Ok(data) => Some((estado.nome.clone(), data)),
+	    Err(err) => { println!("Warning: query for {} failed: {}", estado.nome, err); None }
+	}
+    ).collect();
+
+    let muni_data = sus.municipios.iter().filter_map(
+	|muni| match sus::tests(cache_path, mode, sus::Level::Municipio, &muni.nome,
+			       &muni.estado.to_lowercase()) {
+	    Ok(data) => Some((muni.nome.clone(), data)),
+	    Err(err) => { println!("Warning: query for {} failed: {}", muni.nome, err); None }
+	}
+    ).collect();
+
+    (data, muni_data)
+
+}
+
+/// Test graphs for the configured Brazilian states and municipalities.
+pub fn sus_test_graphs(output: &Output, cache_path: &Path, mode: CacheMode,
+		       settings: &Settings, sus: &SusConfig) -> Result<()> {
+
+    let (data, muni_data) = sus_data(cache_path, mode, sus);
+
+    let mut summed_data = BTreeMap::new();
+
+    for (estado,data) in data.iter() {
+
+	test_graphs(output, settings, &format!("brazil/estados/{}", unidecode(estado)),
+		    estado, data, &vec![])?;
+
+	for (date,tests) in data.present() {
+	    *summed_data.entry(date).or_default() += *tests;
+	}
+
+    }
+
+    let (start,end) = span(&summed_data)?;
+    
+    test_graphs_regions(output, settings, "brazil/pais",
+			"Brazil", &data, &vec![])?;
+    test_graphs(output, settings, "brazil/pais", "Brazil",
+		&Series::from_map(start, end, &summed_data).fill(Tests::default()), &vec![])?;
+
+    for (muni,data) in muni_data.iter() {
+	test_graphs(output, settings, &format!("brazil/municipios/{}", unidecode(muni)),
+		    muni, data, &vec![])?;
+    }
+
+    Ok(())
+
+}
+
+/// Write the graphs for cumulative case counts: absolute and relative
+/// totals, optionally the daily counts adjusted for the day of the
+/// week, and the daily counts, incidence, growth, doubling time and
+/// Rt for each smoothing (Rt is estimated over a window of that
+/// length). The daily counts are corrected for negative values and
+/// jumps first; each correction is logged. Anomalous days are marked
+/// on the daily and incidence graphs, and the daily graphs show the
+/// forecast of the average they plot.
+#[allow(clippy::too_many_arguments)]
+pub fn case_graphs(output: &Output, settings: &Settings, group: &str,
+		   level: &str, var: &str, data: &CasesData,
+		   population: &Population, refs: &Refs) -> Result<()> {
+    let populations = report::populations(data, population)?;
+    graph::cases_graph(output, group, level, var,
+		       &json!({"type":"log"}), &vec![], data, &Overlays::default())?;
+    graph::relative_graph(output, group, level, var,
+			  &json!({"type":"log"}), &vec![],
+			  &data.iter().zip(&populations).map(|((region,series),population)| (region.clone(), incidence(series, *population))
+			  ).collect())?;
+    let daily_data = data.iter().map(|(region,series)| {
+	let (corrected,corrections) = correct_daily(&daily(series), settings.redistribute);
+	for correction in corrections {
+	    println!("Correction in {} for {}: {}", group, region, correction);
+	}
+	(region.clone(), corrected)
+    }).collect::<CasesData>();
+    let anomalous = daily_data.iter().map(
+	|(region,series)| (region.clone(), anomalies(series, ANOMALY_WINDOW, ANOMALY_THRESHOLD))
+    ).collect::<Anomalies>();
+    let smoothing_data = match settings.exclude_anomalies {
+	false => daily_data.clone(),
+	true => daily_data.iter().zip(&anomalous).map(
+	    |((region,series),(_,dates))| (region.clone(), replace_by_median(series, ANOMALY_WINDOW, dates))
+	).collect()
+    };
+    let incidence_overlays = Overlays {
+	anomalies: anomalous.clone(),
+	..Overlays::default()
+    };
+    if settings.weekday_adjustment {
+	graph::daily_adjusted_graph(output, group, level, var, &vec![], &daily_data.iter().map(
+	    |(region,series)| (region.clone(), adjust_weekdays(series, WEEKDAY_WEEKS))
+	).collect())?;
+    }
+    for smoothing in &settings.smoothings {
+	let smoothed = match smoothing {
+	    1 => &daily_data,
+	    _ => &smoothing_data
+	};
+	let averages = smoothed.iter().map(
+	    |(region,series)| (region.clone(), smooth(series, *smoothing, settings.kernel))
+	).collect();
+	let daily_overlays = Overlays {
+	    anomalies: anomalous.clone(),
+	    forecast: forecasts(settings, &averages),
+	    ..Overlays::default()
+	};
+	graph::daily_graph(output, group, level, var, &vec![], *smoothing, settings.kernel,
+			   &averages, &daily_overlays)?;
+	graph::incidence_graph(output, group, level, var, &refs.iter().map(|(n,r)| (*n, *r * *smoothing as f64)).collect(), *smoothing, settings.kernel, &smoothed.iter().zip(&populations).map(
+	    |((region,series),population)| (region.clone(), smooth_sum(&incidence(series, *population), *smoothing, settings.kernel))
+	).collect(), &incidence_overlays)?;
+	if *smoothing != 1 {
+	    for method in &settings.growth_methods {
+		let input = match method {
+		    GrowthMethod::Ratio => &smoothing_data,
+		    GrowthMethod::Regression => &daily_data
+		};
+		graph::growth_graph(output, group, level, var, *smoothing, settings.kernel, *method, &input.iter().map(
+		    |(region,series)| (region.clone(), growth_estimates(*method, settings.kernel, series, *smoothing))
+		).collect())?;
+	    }
+	    graph::doubling_graph(output, group, level, var, *smoothing, settings.kernel,
+				  MAX_DOUBLING_TIME, &smoothing_data.iter().map(
+		|(region,series)| (region.clone(), doubling_times(
+		    &growths(&smooth(series, *smoothing, settings.kernel), *smoothing),
+		    MAX_DOUBLING_TIME))
+	    ).collect())?;
+	    graph::rt_graph(output, group, level, var, *smoothing, &daily_data.iter().map(
+		|(region,series)| (region.clone(), rt::estimate(series, *smoothing, &settings.serial_interval))
+	    ).collect())?;
+	}
+    }
+    Ok(())
+}
+
+/// Write the report of the current reference level of each region
+/// of a cumulative count, and the days to the next level at the
+/// current growth, comparing the incidence over `window` days. Level
+/// changes are alerted, if enabled.
+#[allow(clippy::too_many_arguments)]
+pub fn level_report(output: &Output, settings: &Settings, group: &str, level: &str,
+		    var: &str, data: &CasesData, population: &Population, refs: &Refs,
+		    window: usize) -> Result<()> {
+    let reports = report::level_reports(&data.iter().map(
+	|(region,series)| (region.clone(), daily(series))
+    ).collect(), population, refs, window)?;
+    if let Some(alerts) = &settings.alerts {
+	alert::check(alerts, group, &format!("{}-day incidence of {}", window, var),
+		     &report::levels(refs, window as f64), &reports.iter().map(
+			 |report| alert::Metric {
+			     region: report.region.clone(),
+			     date: report.date.clone(),
+			     value: report.incidence
+			 }
+		     ).collect::<Vec<_>>())?;
+    }
+    report::write_levels(&output.path, group, &format!("Levels of {} by {}", var, level),
+			 window, &reports)
+}
+
+/// Write the graphs for a count of active cases (e.g. hospital
+/// occupancy), with the forecast of the count.
+pub fn active_graphs(output: &Output, settings: &Settings, group: &str,
+		     level: &str, var: &str, data: &CasesData,
+		     population: &Population) -> Result<()> {
+    let populations = report::populations(data, population)?;
+    graph::cases_graph(output, group, level, var, &json!({}),
+		       &vec![(None, 0.0)], data, &Overlays {
+			   forecast: forecasts(settings, data),
+			   ..Overlays::default()
+		       })?;
+    graph::relative_graph(output, group, level, var, &json!({}),
+			  &vec![(None, 0.0)], &data.iter().zip(&populations).map(
+			      |((region,series),population)| (region.clone(), incidence(series, *population))
+			  ).collect())?;
+    for smoothing in &settings.smoothings {
+	graph::daily_graph(output, group, level, var, &vec![(None, 0.0)], *smoothing, settings.kernel, &data.iter().map(
+	    |(region,series)| (region.clone(), smooth(&daily(series), *smoothing, settings.kernel))
+	).collect(), &Overlays::default())?;
+	graph::incidence_graph(output, group, level, var, &vec![], *smoothing, settings.kernel, &data.iter().zip(&populations).map(
+	    |((region,series),population)| (region.clone(), smooth_sum(&daily(&incidence(series, *population)), *smoothing, settings.kernel))
+	).collect(), &Overlays::default())?;
+
+	if *smoothing != 1 {
+	    for method in &settings.growth_methods {
+		graph::growth_graph(output, group, level, var, *smoothing, settings.kernel, *method, &data.iter().map(
+		    |(region,series)| (region.clone(), growth_estimates(*method, settings.kernel, series, *smoothing))
+		).collect())?;
+	    }
+	    graph::doubling_graph(output, group, level, var, *smoothing, settings.kernel,
+				  MAX_DOUBLING_TIME, &data.iter().map(
+		|(region,series)| (region.clone(), doubling_times(
+		    &growths(&smooth(series, *smoothing, settings.kernel), *smoothing),
+		    MAX_DOUBLING_TIME))
+	    ).collect())?;
+	}
+    }
+    Ok(())
+}
+
+/// Write the test graphs for a single region.
+pub fn test_graphs(output: &Output, settings: &Settings,
+		   group: &str, region: &str, data: &TestsData,
+		   refs: &Refs) -> Result<()> {
+
+    for smoothing in &settings.smoothings {
+	graph::tests_graph(output, group, region, *smoothing, settings.kernel,
+			   &smooth(data, *smoothing, settings.kernel),
+			   refs)?;
+    }
+
+    positivity_alerts(settings, group, &[(region.to_string(), data.clone())], refs)?;
+
+    Ok(())
+
+}
+
+/// Write the positivity and test count graphs comparing regions.
+pub fn test_graphs_regions(output: &Output, settings: &Settings, group: &str,
+			   level: &str, data: &[(String,TestsData)],
+			   refs: &Refs) -> Result<()> {
+
+    for smoothing in &settings.smoothings {
+	let averaged_data = data.iter().map(
+	    |(region,data)| (region.clone(), smooth(data, *smoothing, settings.kernel))
+	).collect::<Vec<_>>();
+	graph::test_positivity_graph(output, group, level, *smoothing, settings.kernel,
+				     &averaged_data, refs)?;
+	graph::total_tests_graph(output, group, level, *smoothing, settings.kernel,
+				 &averaged_data)?;
+    }
+
+    positivity_alerts(settings, group, data, refs)?;
+
+    Ok(())
+
+}
+
+
+/// Alert changes of the test positivity level, if enabled.
+fn positivity_alerts(settings: &Settings, group: &str, data: &[(String,TestsData)],
+		     refs: &Refs) -> Result<()> {
+    let levels = report::levels(refs, 1.0);
+    match &settings.alerts {
+	Some(alerts) if !levels.is_empty() => {
+	    alert::check(alerts, group, &format!("{}-day COVID-19 test positivity", POSITIVITY_WINDOW),
+			 &levels, &data.iter().filter_map(|(region,series)| {
+			     let (date,tests) = smooth(series, POSITIVITY_WINDOW, Kernel::Trailing).last()
+				 .map(|(date,tests)| (date, *tests))?;
+			     Some(alert::Metric {
+				 region: region.clone(),
+				 date: format!("{}", date.format("%Y-%m-%d")),
+				 value: tests.positivity()
+			     })
+			 }).collect::<Vec<_>>())?;
+	    Ok(())
+	},
+	_ => Ok(())
+    }
+}
+
+/// The forecasts of `data`, if enabled.
+fn forecasts(settings: &Settings, data: &CasesData) -> EstimateData {
+    match settings.forecast {
+	None => vec![],
+	Some(model) => data.iter().map(
+	    |(region,series)| (region.clone(), forecast::forecast(series, model, FORECAST_WINDOW,
+								   FORECAST_HORIZON))
+	).collect()
+    }
+}
+
+/// Daily growth factors of `data` over `window` days.
+fn growth_estimates(method: GrowthMethod, kernel: Kernel, data: &Series,
+		    window: usize) -> Series<Estimate> {
+    match method {
+	GrowthMethod::Ratio => growths(&smooth(data, window, kernel), window).map(|f| Estimate::exact(*f)),
+	GrowthMethod::Regression => regression_growths(data, window)
+    }
+}
+
+/// Series by region over the dates `start` to `end`, with missing
+/// days filled with the default value.
+fn series_by_region<T>(data: BTreeMap<String,BTreeMap<NaiveDate,T>>,
+		       start: NaiveDate, end: NaiveDate) -> Vec<(String,Series<T>)>
+where T: Clone + Default {
+    data.into_iter().map(
+	|(region,values)| (region, Series::from_map(start, end, &values).fill(T::default()))
+    ).collect()
+}
+
+/// Weekly incidence by age group over the complete ISO weeks between
+/// `start` and `end`, from the daily cases by age group in `data`,
+/// with the population of each age group scaled by `scale`. Age
+/// groups without population (unknown age) are left out.
+fn weekly_incidence(data: &BTreeMap<String,BTreeMap<NaiveDate,f64>>, ages: &Population,
+		    scale: f64, start: NaiveDate, end: NaiveDate) -> WeeklyData {
+    data.iter().filter_map(|(age,days)| {
+	let population = *ages.get(age)? as f64 * scale;
+	let monday = |date: NaiveDate| date - Duration::days(date.weekday().num_days_from_monday() as i64);
+	let mut weeks = NaiveDateRange(monday(start), Some(end)).step_by(7)
+	    .filter(|monday| *monday >= start && *monday + Duration::days(6) <= end)
+	    .map(|monday| (monday, 0.0)).collect::<BTreeMap<_,_>>();
+	for (date,n) in days {
+	    if let Some(week) = weeks.get_mut(&monday(*date)) {
+		*week += n;
+	    }
+	}
+	Some((age.clone(), weeks.into_iter().map(
+	    |(monday,n)| (monday, n * 100000.0 / population)
+	).collect()))
+    }).collect()
+}
+
+
+/// The first and last date in `data`.
+fn span<T>(data: &BTreeMap<NaiveDate,T>) -> Result<(NaiveDate,NaiveDate)> {
+    Ok((*data.keys().next().ok_or(Error::MissingData)?,
+	*data.keys().last().ok_or(Error::MissingData)?))
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use super::super::config::Config;
+
+    fn muni(nis: &str, name: &str, province: Option<&str>, region: &str, cases: &str) -> sciensano::CasesMuni {
+	serde_json::from_value(json!({
+	    "NIS5": nis,
+	    "TX_DESCR_NL": name,
+	    "TX_ADM_DSTR_DESCR_NL": format!("Arrondissement {}", name),
+	    "TX_PROV_DESCR_NL": province,
+	    "TX_RGN_DESCR_NL": region,
+	    "TX_RGN_DESCR_FR": "Région",
+	    "CASES": cases
+	})).unwrap()
+    }
+
+    fn muni_data() -> Vec<Vec<sciensano::CasesMuni>> {
+	(0..20).map(|day| vec![
+	    muni("11002", "Antwerpen", Some("Provincie Antwerpen"), "Vlaams Gewest", &(100 + 10 * day).to_string()),
+	    muni("24062", "Leuven", Some("Provincie Vlaams-Brabant"), "Vlaams Gewest", &(50 + 5 * day).to_string()),
+	    muni("62063", "Luik", Some("Provincie Luik"), "Waals Gewest", &(80 + 4 * day).to_string()),
+	    muni("21004", "Brussel", None, "Brussels Hoofdstedelijk Gewest", &(60 + 3 * day).to_string()),
+	    muni("13053", "Mol", Some("Provincie Antwerpen"), "Vlaams Gewest", "<5")
+	]).collect()
+    }
+
+    fn names(map: &MapData) -> Vec<&str> {
+	map.iter().map(|(name,_,_)| name.as_str()).collect()
+    }
+
+    #[test]
+    fn missing_population() {
+	let config : Config = serde_json::from_value(json!({
+	    "sciensano": {"population": {"region": {"Brussels": 1218255, "Flanders": 6629143}}}
+	})).unwrap();
+	let population = &config.sciensano.population["region"];
+	let data = ["Brussels", "Flanders", "Wallonia"].iter().map(
+	    |region| (region.to_string(), Series::from_values(NaiveDate::from_ymd(2020, 10, 1), vec![1.0, 2.0, 4.0]))
+	).collect::<CasesData>();
+	let output = Output::new(std::env::temp_dir().join(format!("covid19-missing-{}", std::process::id())),
+				 graph::Scripts::Cdn, vec![], vec![]);
+	let settings = Settings {
+	    smoothings: vec![1],
+	    redistribute: None,
+	    exclude_anomalies: false,
+	    forecast: None,
+	    kernel: Kernel::Trailing,
+	    weekday_adjustment: false,
+	    growth_methods: vec![],
+	    serial_interval: config.serial_interval,
+	    alerts: None
+	};
+	let missing = |result: Result<()>| match result {
+	    Err(Error::MissingRegion(region)) => assert_eq!(region, "Wallonia"),
+	    result => panic!("expected a missing region, got {:?}", result)
+	};
+	missing(case_graphs(&output, &settings, "test", "region", "cases", &data, population, &vec![]));
+	missing(active_graphs(&output, &settings, "test", "region", "cases", &data, population));
+	missing(report::level_reports(&data, population, &vec![], 14).map(|_| ()));
+	assert!(!output.path.exists());
+    }
+
+    #[test]
+    fn muni_maps() {
+	let data = muni_data();
+	let cases = sciensano::cases_muni_by_nis(&data);
+	let population = vec![
+	    ("11002", 500000), ("24062", 100000), ("62063", 200000), ("21004", 180000), ("13053", 40000)
+	].into_iter().map(|(nis,n)| (nis.to_string(), n)).collect::<Population>();
+
+	let (provinces, missing) = muni_map(sciensano::Level::Province, &data, &cases, &population, 14);
+	assert_eq!(missing, 0);
+	assert_eq!(names(&provinces), vec!["Antwerpen", "Brussels Hoofdstedelijk Gewest", "Luik", "Vlaams-Brabant"]);
+	let (_, nis, incidence) = &provinces[0];
+	assert_eq!(nis, &vec!["11002".to_string(), "13053".to_string()]);
+	// 140 cases in 14 days in Antwerpen, over the population of both
+	// municipalities.
+	assert!((incidence.last().unwrap().1 - 140.0 * 100000.0 / 540000.0).abs() < 1e-9);
+
+	let (regions, missing) = muni_map(sciensano::Level::Region, &data, &cases, &population, 14);
+	assert_eq!(missing, 0);
+	assert_eq!(names(&regions), vec!["Brussels Hoofdstedelijk Gewest", "Vlaams Gewest", "Waals Gewest"]);
+
+	// Regions with a municipality without population are left out.
+	let partial = population.into_iter().filter(|(nis,_)| nis != "24062").collect();
+	let (regions, missing) = muni_map(sciensano::Level::Region, &data, &cases, &partial, 14);
+	assert_eq!(missing, 1);
+	assert_eq!(names(&regions), vec!["Brussels Hoofdstedelijk Gewest", "Waals Gewest"]);
+    }
+
+}
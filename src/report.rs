@@ -0,0 +1,164 @@
+//! Reports on the position of each region relative to the reference
+//! levels, written as a table page and as JSON next to the graphs.
+
+use std::{io,fs};
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use serde::Serialize;
+
+use super::error::{Result,Error};
+use super::graph::{CasesData,Population,Refs};
+use super::series::{Series,sum,incidence,regression_growths};
+
+
+/// The current level of a region, and when it will reach the next
+/// one at its current growth rate.
+#[derive(Serialize,Clone,Debug)]
+pub struct LevelReport {
+    pub region: String,
+    /// Date of the last value.
+    pub date: String,
+    /// Incidence per 100k over the report window.
+    pub incidence: f64,
+    /// The highest level reached, or "below" the lowest one.
+    pub level: String,
+    /// First date at the current level.
+    pub since: String,
+    /// Estimated daily growth factor.
+    pub growth: Option<f64>,
+    /// The next level crossed at the current growth rate, if growing
+    /// towards a higher or shrinking towards a lower level.
+    pub crossing: Option<Crossing>,
+}
+
+#[derive(Serialize,Clone,Debug)]
+pub struct Crossing {
+    /// The level after crossing.
+    pub level: String,
+    /// The threshold crossed, as incidence over the report window.
+    pub threshold: f64,
+    pub days: f64,
+}
+
+
+/// Level reports for the daily counts in `data`, comparing their
+/// incidence over `window` days to the named daily `refs`. Fails if a
+/// region is missing from `population`.
+pub fn level_reports(data: &CasesData, population: &Population,
+		     refs: &Refs, window: usize) -> Result<Vec<LevelReport>> {
+    let levels = levels(refs, window as f64);
+    Ok(data.iter().zip(populations(data, population)?).filter_map(
+	|((region,series),population)| level_report(region, series, population, &levels, window)
+    ).collect())
+}
+
+/// The population of each region of `data`, in order. Fails for the
+/// first region missing from `population`.
+pub fn populations(data: &CasesData, population: &Population) -> Result<Vec<u64>> {
+    data.iter().map(
+	|(region,_)| population.get(region).copied().ok_or_else(|| Error::MissingRegion(region.clone()))
+    ).collect()
+}
+
+/// The named `refs` multiplied by `scale`, in increasing order.
+pub fn levels(refs: &Refs, scale: f64) -> Vec<(&'static str,f64)> {
+    let mut levels = refs.iter().filter_map(
+	|(name,r)| name.map(|name| (name, *r * scale))
+    ).collect::<Vec<_>>();
+    levels.sort_by(|a,b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+    levels
+}
+
+/// The name of the `level`th of `levels`, counting from "below" the
+/// lowest one.
+pub fn level_name(levels: &[(&str,f64)], level: usize) -> String {
+    match level {
+	0 => format!("below {}", levels.first().map_or("", |(name,_)| name)),
+	n => levels[n-1].0.to_string()
+    }
+}
+
+fn level_report(region: &str, daily: &Series, population: u64,
+		levels: &[(&str,f64)], window: usize) -> Option<LevelReport> {
+
+    let incidences = sum(&incidence(daily, population), window);
+    let level = |value: f64| levels.iter().filter(|(_,threshold)| value >= *threshold).count();
+    let name = |level: usize| level_name(levels, level);
+
+    let (date,value) = incidences.last()?;
+    let current = level(*value);
+    let since = incidences.iter().collect::<Vec<_>>().into_iter().rev()
+	.take_while(|(_,v)| v.map_or(false, |v| level(*v) == current))
+	.last().map_or(date, |(date,_)| date);
+
+    let growth = regression_growths(daily, window).last().map(|(_,growth)| growth.value);
+    let target = match growth {
+	Some(f) if f > 1.0 && current < levels.len() => Some(current + 1),
+	Some(f) if f < 1.0 && current > 0 => Some(current - 1),
+	_ => None
+    };
+
+    Some(LevelReport {
+	region: region.to_string(),
+	date: format!("{}", date.format("%Y-%m-%d")),
+	incidence: *value,
+	level: name(current),
+	since: format!("{}", since.format("%Y-%m-%d")),
+	growth,
+	crossing: target.and_then(|target| {
+	    let threshold = levels[target.max(current) - 1].1;
+	    let days = (threshold / value).ln() / growth?.ln();
+	    match days.is_finite() {
+		true => Some(Crossing { level: name(target), threshold, days }),
+		false => None
+	    }
+	})
+    })
+
+}
+
+
+/// `levels.html` and `levels.json`: the level reports for a group.
+pub fn write_levels(graph_path: &Path, group: &str, title: &str, window: usize,
+		    reports: &[LevelReport]) -> Result<()> {
+
+    let graph_path = graph_path.join(group);
+    fs::create_dir_all(&graph_path)?;
+
+    serde_json::to_writer_pretty(io::BufWriter::new(File::create(graph_path.join("levels.json"))?),
+				 reports)?;
+
+    let mut out = io::BufWriter::new(File::create(graph_path.join("levels.html"))?);
+
+    write!(out, "<!DOCTYPE html><html><head>")?;
+    write!(out, "<meta charset=\"UTF-8\">")?;
+    write!(out, "<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">")?;
+    write!(out, "<title>{}</title>", title)?;
+    write!(out, "<style>table {{ border-collapse: collapse; }} \
+		 th, td {{ padding: 0.2em 0.6em; text-align: right; }} \
+		 th:first-child, td:first-child {{ text-align: left; }}</style>")?;
+    write!(out, "</head>")?;
+    write!(out, "<body>")?;
+    write!(out, "<h1>{}</h1>", title)?;
+    write!(out, "<table><tr><th>Region</th><th>Date</th><th>{}-day incidence</th>\
+		 <th>Level</th><th>Since</th><th>Daily growth</th>\
+		 <th>Next level</th><th>In days</th></tr>", window)?;
+
+    for report in reports {
+	write!(out, "<tr><td>{}</td><td>{}</td><td>{:.1}</td><td>{}</td><td>{}</td><td>{}</td>",
+	       report.region, report.date, report.incidence, report.level, report.since,
+	       report.growth.map_or(String::new(), |f| format!("{:.3}", f)))?;
+	match &report.crossing {
+	    Some(crossing) => write!(out, "<td>{}</td><td>{:.0}</td></tr>", crossing.level, crossing.days)?,
+	    None => write!(out, "<td></td><td></td></tr>")?
+	}
+    }
+
+    write!(out, "</table>")?;
+    write!(out, "</body></html>")?;
+
+    Ok(())
+
+}
@@ -0,0 +1,252 @@
+//! Estimation of the effective reproduction number (Rt), following
+//! Cori et al. (2013): a Bayesian estimate over a sliding window,
+//! assuming a gamma prior and a constant Rt within the window.
+
+use serde::{Serialize,Deserialize};
+
+use super::series::{Series,Estimate};
+use super::error::{Result,Error};
+
+
+/// Shape and scale of the gamma prior on Rt (as in EpiEstim).
+const PRIOR_SHAPE: f64 = 1.0;
+const PRIOR_SCALE: f64 = 5.0;
+
+/// Minimum number of cases in a window to report an estimate;
+/// below this the posterior is dominated by the prior.
+const MIN_CASES: f64 = 12.0;
+
+/// Probability mass of the serial interval distribution that is
+/// covered by its discretization.
+const SI_COVERAGE: f64 = 0.999;
+
+
+/// Gamma-distributed serial interval, in days.
+#[derive(Serialize,Deserialize,Clone,Copy,Debug,PartialEq)]
+pub struct SerialInterval {
+    pub mean: f64,
+    pub sd: f64,
+}
+
+impl Default for SerialInterval {
+    /// Nishiura et al. (2020).
+    fn default() -> Self {
+	Self { mean: 4.7, sd: 2.9 }
+    }
+}
+
+impl SerialInterval {
+
+    /// Check that the mean and standard deviation are positive, as
+    /// the gamma distribution requires.
+    pub fn validate(&self) -> Result<()> {
+	match self.mean > 0.0 && self.sd > 0.0 {
+	    true => Ok(()),
+	    false => Err(Error::InvalidConfig(format!(
+		"the serial interval needs a positive mean and sd (got {} and {})", self.mean, self.sd)))
+	}
+    }
+
+    /// The probability of a serial interval of `s` days, for `s`
+    /// from zero up to where the distribution is (nearly) exhausted.
+    /// The interval is at least one day, so the first element is zero.
+    pub fn distribution(&self) -> Vec<f64> {
+	let shape = (self.mean / self.sd).powi(2);
+	let scale = self.sd.powi(2) / self.mean;
+	let mut result = vec![0.0];
+	let mut prev = 0.0;
+	while prev < SI_COVERAGE {
+	    let next = gamma_p(shape, (result.len() as f64 + 0.5) / scale);
+	    result.push(next - prev);
+	    prev = next;
+	}
+	let total : f64 = result.iter().sum();
+	result.iter().map(|p| p / total).collect()
+    }
+
+}
+
+
+/// Rt over the `window` days up to each date, from daily incidence,
+/// with its 95% credible interval. Negative counts are taken as zero;
+/// windows with missing days or too few cases have no estimate.
+pub fn estimate(daily: &Series, window: usize, si: &SerialInterval) -> Series<Estimate> {
+
+    let w = si.distribution();
+    let values = daily.values();
+    let cases = values.iter().map(|v| v.unwrap_or(0.0).max(0.0)).collect::<Vec<_>>();
+
+    /* Total infectiousness of the earlier cases on each day. */
+    let infectiousness = (0..cases.len()).map(
+	|t| w.iter().enumerate().skip(1).take_while(|(s,_)| *s <= t)
+	    .map(|(s,p)| cases[t-s] * p).sum::<f64>()
+    ).collect::<Vec<_>>();
+
+    Series::new(daily.start(), (0..cases.len()).map(|t| {
+	if t + 1 < window || values[t+1-window..=t].iter().any(Option::is_none) {
+	    return None;
+	}
+	let n : f64 = cases[t+1-window..=t].iter().sum();
+	let lambda : f64 = infectiousness[t+1-window..=t].iter().sum();
+	match n < MIN_CASES || lambda <= 0.0 {
+	    true => None,
+	    false => {
+		let shape = PRIOR_SHAPE + n;
+		let scale = 1.0 / (1.0 / PRIOR_SCALE + lambda);
+		Some(Estimate {
+		    value: shape * scale,
+		    lower: gamma_quantile(shape, scale, 0.025),
+		    upper: gamma_quantile(shape, scale, 0.975)
+		})
+	    }
+	}
+    }).collect())
+
+}
+
+
+/// Quantile `p` of the gamma distribution, by bisection.
+fn gamma_quantile(shape: f64, scale: f64, p: f64) -> f64 {
+    let mut lo = 0.0;
+    let mut hi = shape.max(1.0);
+    while gamma_p(shape, hi) < p {
+	lo = hi;
+	hi *= 2.0;
+    }
+    for _ in 0..100 {
+	let mid = (lo + hi) / 2.0;
+	match gamma_p(shape, mid) < p {
+	    true => lo = mid,
+	    false => hi = mid
+	}
+    }
+    (lo + hi) / 2.0 * scale
+}
+
+/// Regularized lower incomplete gamma function P(a, x).
+fn gamma_p(a: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+	return 0.0;
+    }
+    let ln_prefix = a * x.ln() - x - ln_gamma(a);
+    match x < a + 1.0 {
+	true => {
+	    /* Series expansion. */
+	    let mut term = 1.0 / a;
+	    let mut sum = term;
+	    let mut n = a;
+	    while term.abs() > sum.abs() * 1e-15 {
+		n += 1.0;
+		term *= x / n;
+		sum += term;
+	    }
+	    sum * ln_prefix.exp()
+	},
+	false => {
+	    /* Continued fraction for Q(a, x) (modified Lentz). */
+	    let tiny = 1e-300;
+	    let mut b = x + 1.0 - a;
+	    let mut c = 1.0 / tiny;
+	    let mut d = 1.0 / b;
+	    let mut h = d;
+	    for i in 1..10000 {
+		let an = -(i as f64) * (i as f64 - a);
+		b += 2.0;
+		d = an * d + b;
+		if d.abs() < tiny { d = tiny; }
+		c = b + an / c;
+		if c.abs() < tiny { c = tiny; }
+		d = 1.0 / d;
+		let delta = d * c;
+		h *= delta;
+		if (delta - 1.0).abs() < 1e-15 {
+		    break;
+		}
+	    }
+	    1.0 - ln_prefix.exp() * h
+	}
+    }
+}
+
+/// Logarithm of the gamma function (Lanczos approximation).
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFS: [f64; 9] = [
+	0.999_999_999_999_809_9,
+	676.520_368_121_885_1,
+	-1_259.139_216_722_402_8,
+	771.323_428_777_653_1,
+	-176.615_029_162_140_6,
+	12.507_343_278_686_905,
+	-0.138_571_095_265_720_12,
+	9.984_369_578_019_572e-6,
+	1.505_632_735_149_311_6e-7,
+    ];
+    match x < 0.5 {
+	true => (std::f64::consts::PI / (std::f64::consts::PI * x).sin()).ln() - ln_gamma(1.0 - x),
+	false => {
+	    let x = x - 1.0;
+	    let t = x + 7.5;
+	    let sum = COEFFS.iter().enumerate().skip(1).fold(
+		COEFFS[0], |sum,(i,c)| sum + c / (x + i as f64));
+	    0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+	}
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn assert_close(value: f64, expected: f64) {
+	assert!((value - expected).abs() < 1e-9, "{} != {}", value, expected);
+    }
+
+    #[test]
+    fn ln_gamma_values() {
+	assert_close(ln_gamma(1.0), 0.0);
+	assert_close(ln_gamma(2.0), 0.0);
+	assert_close(ln_gamma(5.0), 24f64.ln());
+	assert_close(ln_gamma(10.0), 362880f64.ln());
+	assert_close(ln_gamma(0.5), 0.5 * std::f64::consts::PI.ln());
+	assert_close(ln_gamma(1.5), (std::f64::consts::PI.sqrt() / 2.0).ln());
+	assert_close(ln_gamma(2.5), (0.75 * std::f64::consts::PI.sqrt()).ln());
+    }
+
+    #[test]
+    fn gamma_quantiles() {
+	// Exponential distribution.
+	assert_close(gamma_p(1.0, 2.0), 1.0 - (-2f64).exp());
+	assert_close(gamma_quantile(1.0, 1.0, 0.5), 2f64.ln());
+	assert_close(gamma_quantile(1.0, 2.0, 0.975), -2.0 * 0.025f64.ln());
+	// Chi-squared distributions with 1 and 10 degrees of freedom.
+	assert!((gamma_quantile(0.5, 2.0, 0.975) - 5.023886187).abs() < 1e-6);
+	assert!((gamma_quantile(5.0, 2.0, 0.025) - 3.246972780).abs() < 1e-6);
+	assert!((gamma_quantile(5.0, 2.0, 0.975) - 20.483177351).abs() < 1e-6);
+    }
+
+    #[test]
+    fn serial_interval() {
+	let w = SerialInterval::default().distribution();
+	assert_eq!(w[0], 0.0);
+	assert_close(w.iter().sum(), 1.0);
+	let mean : f64 = w.iter().enumerate().map(|(s,p)| s as f64 * p).sum();
+	assert!((mean - 4.7).abs() < 0.1, "mean {}", mean);
+	assert!(SerialInterval::default().validate().is_ok());
+	assert!(SerialInterval { mean: 0.0, sd: 2.9 }.validate().is_err());
+	assert!(SerialInterval { mean: 4.7, sd: -1.0 }.validate().is_err());
+    }
+
+    #[test]
+    fn constant_incidence() {
+	let daily = Series::from_values(NaiveDate::from_ymd(2020, 10, 1), vec![100.0; 60]);
+	let rt = estimate(&daily, 7, &SerialInterval::default());
+	let (_,last) = rt.last().unwrap();
+	assert!((last.value - 1.0).abs() < 0.01, "Rt {}", last.value);
+	assert!(last.lower < 1.0 && last.upper > 1.0);
+	assert_eq!(rt.get(NaiveDate::from_ymd(2020, 10, 6)), None);
+    }
+
+}
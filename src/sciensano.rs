@@ -1,6 +1,9 @@
+//! Sciensano (Belgium) open data.
+
 use std::{io,fs};
 use std::fs::File;
 use std::path::Path;
+use std::collections::BTreeMap;
 
 use serde::{Serialize,Deserialize,de::DeserializeOwned};
 use chrono::{DateTime,Local,Duration};
@@ -8,17 +11,21 @@ use chrono::naive::NaiveDate;
 use encoding_rs::mem::decode_latin1;
 
 use super::error::{Result,Error};
+use super::cache::CacheMode;
+use super::series::Series;
 use super::NaiveDateRange;
 
 
 #[derive(Serialize,Deserialize,Debug)]
 #[serde(rename_all = "UPPERCASE")]
 pub struct CasesMuni {
-    tx_descr_nl: Option<String>,
-    tx_adm_dstr_descr_nl: Option<String>,
-    tx_prov_descr_nl: Option<String>,
-    tx_rgn_descr_nl: Option<String>,
-    cases: String
+    /// Missing in caches written before the NIS codes were kept.
+    pub nis5: Option<String>,
+    pub tx_descr_nl: Option<String>,
+    pub tx_adm_dstr_descr_nl: Option<String>,
+    pub tx_prov_descr_nl: Option<String>,
+    pub tx_rgn_descr_nl: Option<String>,
+    pub cases: String
 }
 
 #[derive(Serialize,Deserialize,Debug)]
@@ -47,6 +54,16 @@ pub struct Hospitalizations {
     pub new_out: u64,
 }
 
+#[derive(Serialize,Deserialize,Debug)]
+#[serde(rename_all = "UPPERCASE")]
+pub struct Mortality {
+    pub date: Option<String>,
+    pub region: Option<String>,
+    pub agegroup: Option<String>,
+    pub sex: Option<String>,
+    pub deaths: u64
+}
+
 #[derive(Serialize,Deserialize,Debug)]
 #[serde(rename_all = "UPPERCASE")]
 pub struct Tests {
@@ -57,6 +74,8 @@ pub struct Tests {
     pub tests_all_pos: u64,
 }
 
+#[derive(Serialize,Deserialize,Debug,Clone,Copy,PartialEq,Eq,PartialOrd,Ord)]
+#[serde(rename_all = "lowercase")]
 pub enum Level {
     Municipality,
     District,
@@ -65,6 +84,9 @@ pub enum Level {
     Country
 }
 
+/// The Dutch name of the Brussels region, which is in no province.
+const BRUSSELS: &str = "Brussels Hoofdstedelijk Gewest";
+
 impl Level {
 
     pub fn name(&self) -> &'static str {
@@ -91,37 +113,82 @@ impl Level {
 	}
     }
 
+    /// The name of the region at this level containing the municipality
+    /// of `cases`, as in `filter_muni`. Brussels, which is not in a
+    /// province, counts as one.
+    pub fn region_of(&self, cases: &CasesMuni) -> Option<String> {
+	match self {
+	    Self::Municipality => cases.tx_descr_nl.clone(),
+	    Self::District => cases.tx_adm_dstr_descr_nl.clone(),
+	    Self::Province => match &cases.tx_prov_descr_nl {
+		Some(v) => Some(v.trim_start_matches("Provincie ").to_string()),
+		None => cases.tx_rgn_descr_nl.clone().filter(|v| v == BRUSSELS)
+	    },
+	    Self::Region => cases.tx_rgn_descr_nl.clone(),
+	    Self::Country => Some("Belgium".to_string())
+	}
+    }
+
 }
 
 
-pub fn cases_muni_series<F>(data: &Vec<Vec<CasesMuni>>,filter: F) -> Vec<Option<u64>>
+/// Cumulative cases per day, summed over the municipalities matching
+/// `filter`. Days where all matching counts are hidden ("<5") are missing.
+pub fn cases_muni_series<F>(data: &Vec<Vec<CasesMuni>>,filter: F) -> Series
 where F: for<'r> Fn(&'r CasesMuni) -> bool {
-    data.iter().map(
+    Series::new(NaiveDate::from_ymd(2020, 3, 31), data.iter().map(
 	|cs| cs.iter().filter(|cs| filter(*cs))
 	    .fold(None, |a,b| match b.cases.as_str() {
 		"<5" => a,
 		n => Some(a.unwrap_or(0) + n.parse::<u64>()
 			  .expect(&format!("failed to parse number of cases {:?}!", n)))
-	    })
-    ).collect()
+	    }).map(|n| n as f64)
+    ).collect())
 }
 
 
-pub fn cases_muni(cache_path: &Path) -> Result<Vec<Vec<CasesMuni>>> {
-    NaiveDateRange(NaiveDate::from_ymd(2020, 3, 31),
-		   Some(Local::today().naive_local()))
-	.map(|date| Ok(cases_muni_per_day(cache_path, date)?
-		       .unwrap_or(vec![])))
-	.collect()
+/// Cumulative cases per day by municipality NIS code. Days where the
+/// count is hidden ("<5") are missing.
+pub fn cases_muni_by_nis(data: &[Vec<CasesMuni>]) -> BTreeMap<String,Series> {
+    let mut values = BTreeMap::new();
+    for (day,cs) in data.iter().enumerate() {
+	for cases in cs {
+	    if let (Some(nis), Ok(n)) = (&cases.nis5, cases.cases.parse::<u64>()) {
+		let series = values.entry(nis.clone()).or_insert_with(Vec::new);
+		series.resize(day, None);
+		series.push(Some(n as f64));
+	    }
+	}
+    }
+    values.into_iter().map(|(nis,mut series)| {
+	series.resize(data.len(), None);
+	(nis, Series::new(NaiveDate::from_ymd(2020, 3, 31), series))
+    }).collect()
 }
 
 
+/// Cumulative cases by municipality, per day since 2020-03-31.
+pub fn cases_muni(cache_path: &Path, mode: CacheMode) -> Result<Vec<Vec<CasesMuni>>> {
+    let data = NaiveDateRange(NaiveDate::from_ymd(2020, 3, 31),
+			      Some(Local::today().naive_local()))
+	.map(|date| cases_muni_per_day(cache_path, date, mode))
+	.collect::<Result<Vec<_>>>()?;
+    if mode == CacheMode::Offline {
+	let last = cases_muni_dates().zip(&data).filter(|(_,cs)| cs.is_some()).last()
+	    .ok_or_else(|| Error::NotCached("COVID19BE_CASES_MUNI_CUM".to_string()))?.0;
+	println!("Offline: latest municipality data is for {}", last);
+    }
+    Ok(data.into_iter().map(|cs| cs.unwrap_or(vec![])).collect())
+}
+
+
+/// The dates corresponding to the days returned by `cases_muni`.
 pub fn cases_muni_dates() -> NaiveDateRange {
     NaiveDateRange(NaiveDate::from_ymd(2020, 3, 31), None)
 }
 
 
-fn cases_muni_per_day(cache_path: &Path, date: NaiveDate)
+fn cases_muni_per_day(cache_path: &Path, date: NaiveDate, mode: CacheMode)
 		      -> Result<Option<Vec<CasesMuni>>> {
 
     let cache_path = cache_path.join("sciensano/cases");
@@ -133,9 +200,11 @@ fn cases_muni_per_day(cache_path: &Path, date: NaiveDate)
 	let modified : DateTime<Local> = fs::metadata(&cache_file)?.modified()?.into();
 	let maturity = modified.date().naive_local() - date.succ();
 	let age = Local::now() - modified;
-	if age < Duration::minutes(30) || maturity > Duration::days(4) {
+	if mode.use_cached(age < Duration::minutes(30)) || maturity > Duration::days(4) {
 	    return Ok(serde_json::from_reader(io::BufReader::new(File::open(&cache_file)?))?);
 	}
+    } else if mode == CacheMode::Offline {
+	return Ok(None);
     }
 
     let data = download_cases_muni_per_day(date)?;
@@ -146,26 +215,36 @@ fn cases_muni_per_day(cache_path: &Path, date: NaiveDate)
 }
 
 
-pub fn cases_agesex(cache_path: &Path) -> Result<Vec<CasesAgeSex>> {
+/// Daily cases by province, region, age group and sex.
+pub fn cases_agesex(cache_path: &Path, mode: CacheMode) -> Result<Vec<CasesAgeSex>> {
     cached("https://epistat.sciensano.be/Data/COVID19BE_CASES_AGESEX.json",
-	   cache_path, "COVID19BE_CASES_AGESEX.json", Duration::minutes(30))
+	   cache_path, "COVID19BE_CASES_AGESEX.json", Duration::minutes(30), mode)
+}
+
+
+/// Daily deaths by region, age group and sex.
+pub fn mortality(cache_path: &Path, mode: CacheMode) -> Result<Vec<Mortality>> {
+    cached("https://epistat.sciensano.be/Data/COVID19BE_MORT.json",
+	   cache_path, "COVID19BE_MORT.json", Duration::minutes(30), mode)
 }
 
 
-pub fn tests(cache_path: &Path) -> Result<Vec<Tests>> {
+/// Daily number of tests by province and region.
+pub fn tests(cache_path: &Path, mode: CacheMode) -> Result<Vec<Tests>> {
     cached("https://epistat.sciensano.be/Data/COVID19BE_tests.json",
-	   cache_path, "COVID19BE_tests.json", Duration::minutes(30))
+	   cache_path, "COVID19BE_tests.json", Duration::minutes(30), mode)
 }
 
 
-pub fn hospitalizations(cache_path: &Path) -> Result<Vec<Hospitalizations>> {
+/// Daily hospitalization figures by province and region.
+pub fn hospitalizations(cache_path: &Path, mode: CacheMode) -> Result<Vec<Hospitalizations>> {
     cached("https://epistat.sciensano.be/Data/COVID19BE_HOSP.json",
-	   cache_path, "COVID19BE_HOSP.json", Duration::minutes(30))
+	   cache_path, "COVID19BE_HOSP.json", Duration::minutes(30), mode)
 }
 
 
 fn cached<T>(url: &str, cache_path: &Path, filename: &str,
-	     max_age: Duration) -> Result<Vec<T>>
+	     max_age: Duration, mode: CacheMode) -> Result<Vec<T>>
 where T: Serialize + DeserializeOwned {
 
     let cache_path = cache_path.join("sciensano");
@@ -173,11 +252,13 @@ where T: Serialize + DeserializeOwned {
 
     if cache_file.exists() {
 	let modified : DateTime<Local> = fs::metadata(&cache_file)?.modified()?.into();
-	if Local::now() - modified < max_age {
+	if mode.use_cached(Local::now() - modified < max_age) {
+	    mode.report(filename, &cache_file)?;
 	    return Ok(serde_json::from_reader(io::BufReader::new(File::open(&cache_file)?))?);
 	}
     }
 
+    mode.download(filename)?;
     println!("Downloading {}...", filename);
     let data = reqwest::blocking::get(url)?.json()?;
 
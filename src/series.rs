@@ -0,0 +1,1004 @@
+//! Daily time series and the transformations used on them.
+
+use std::fmt;
+use std::ops::{Add,AddAssign,Sub,Mul,Div};
+use std::collections::BTreeMap;
+
+use chrono::{Datelike,Duration,naive::NaiveDate};
+
+use super::NaiveDateRange;
+
+
+/// A daily time series: one value per day from `start`, without
+/// gaps in the dates. Missing values are explicitly `None`.
+#[derive(Clone,Debug,PartialEq)]
+pub struct Series<T = f64> {
+    start: NaiveDate,
+    values: Vec<Option<T>>,
+}
+
+/// Test results for a single day (or averaged over several days).
+#[derive(Clone,Copy,Debug,Default,PartialEq)]
+pub struct Tests {
+    pub positive: f64,
+    pub negative: f64,
+    pub total: f64,
+}
+
+/// An estimated value with the bounds of its interval.
+#[derive(Clone,Copy,Debug,Default,PartialEq)]
+pub struct Estimate {
+    pub value: f64,
+    pub lower: f64,
+    pub upper: f64,
+}
+
+/// A correction to a day in a daily count series.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Correction {
+    /// A negative count, set to zero.
+    Negative { date: NaiveDate, value: f64 },
+    /// A count far above the median of the preceding days.
+    Jump { date: NaiveDate, value: f64, expected: f64 },
+}
+
+/// Weighting of the days in a smoothing window.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Kernel {
+    /// Equal weights for the window up to each date.
+    Trailing,
+    /// Equal weights for a window centered on each date.
+    Centered,
+    /// Linearly decreasing weights away from each date, centered.
+    Triangular,
+    /// Exponentially decreasing weights for earlier dates, with the
+    /// same mean age as a trailing window of the same length.
+    Exponential,
+}
+
+/// Method for estimating daily growth factors.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum GrowthMethod {
+    /// Ratio of two smoothed values (`growths`).
+    Ratio,
+    /// Poisson log-linear regression (`regression_growths`).
+    Regression,
+}
+
+
+impl<T> Series<T> {
+
+    pub fn new(start: NaiveDate, values: Vec<Option<T>>) -> Self {
+	Self { start, values }
+    }
+
+    /// A series without missing values.
+    pub fn from_values<I>(start: NaiveDate, values: I) -> Self
+    where I: IntoIterator<Item = T> {
+	Self::new(start, values.into_iter().map(Some).collect())
+    }
+
+    /// The values in `map` from `start` up to and including `end`;
+    /// dates not in `map` are missing.
+    pub fn from_map(start: NaiveDate, end: NaiveDate,
+		    map: &BTreeMap<NaiveDate,T>) -> Self
+    where T: Clone {
+	Self::new(start, NaiveDateRange(start, Some(end))
+		  .map(|date| map.get(&date).cloned()).collect())
+    }
+
+    pub fn start(&self) -> NaiveDate {
+	self.start
+    }
+
+    /// The last date in the series (the day before `start` if the
+    /// series is empty).
+    pub fn end(&self) -> NaiveDate {
+	self.start + Duration::days(self.values.len() as i64 - 1)
+    }
+
+    pub fn len(&self) -> usize {
+	self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+	self.values.is_empty()
+    }
+
+    pub fn dates(&self) -> NaiveDateRange {
+	NaiveDateRange(self.start, Some(self.end()))
+    }
+
+    pub fn values(&self) -> &[Option<T>] {
+	&self.values
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (NaiveDate,Option<&T>)> {
+	self.dates().zip(self.values.iter().map(|v| v.as_ref()))
+    }
+
+    /// The dates and values of the non-missing values.
+    pub fn present(&self) -> impl Iterator<Item = (NaiveDate,&T)> {
+	self.iter().filter_map(|(date,v)| v.map(|v| (date, v)))
+    }
+
+    pub fn get(&self, date: NaiveDate) -> Option<&T> {
+	self.index(date).and_then(|i| self.values[i].as_ref())
+    }
+
+    /// The last non-missing value.
+    pub fn last(&self) -> Option<(NaiveDate,&T)> {
+	self.present().last()
+    }
+
+    pub fn map<U,F>(&self, mut f: F) -> Series<U>
+    where F: FnMut(&T) -> U {
+	Series::new(self.start, self.values.iter().map(|v| v.as_ref().map(&mut f)).collect())
+    }
+
+    /// Replace missing values by `value`.
+    pub fn fill(&self, value: T) -> Self
+    where T: Clone {
+	Self::new(self.start, self.values.iter().map(
+	    |v| Some(v.clone().unwrap_or_else(|| value.clone()))
+	).collect())
+    }
+
+    /// The series over the dates `start` to `end`, missing where the
+    /// original series has no value.
+    pub fn reindex(&self, start: NaiveDate, end: NaiveDate) -> Self
+    where T: Clone {
+	Self::new(start, NaiveDateRange(start, Some(end))
+		  .map(|date| self.get(date).cloned()).collect())
+    }
+
+    /// Both series reindexed to the union of their dates.
+    pub fn align<U>(&self, other: &Series<U>) -> (Self, Series<U>)
+    where T: Clone, U: Clone {
+	let (start,end) = self.union(other);
+	(self.reindex(start, end), other.reindex(start, end))
+    }
+
+    /// Shift the series `days` days forward in time (backward if
+    /// negative): the value for date `d` becomes that for `d + days`.
+    pub fn lag(&self, days: i64) -> Self
+    where T: Clone {
+	Self::new(self.start + Duration::days(days), self.values.clone())
+    }
+
+    /// Combine two series day by day over the union of their dates;
+    /// missing where either value is missing.
+    pub fn zip_with<U,V,F>(&self, other: &Series<U>, f: F) -> Series<V>
+    where F: Fn(&T,&U) -> V {
+	let (start,end) = self.union(other);
+	Series::new(start, NaiveDateRange(start, Some(end)).map(
+	    |date| match (self.get(date), other.get(date)) {
+		(Some(a), Some(b)) => Some(f(a, b)),
+		_ => None
+	    }
+	).collect())
+    }
+
+    fn index(&self, date: NaiveDate) -> Option<usize> {
+	match (date - self.start).num_days() {
+	    i if i < 0 || i as usize >= self.values.len() => None,
+	    i => Some(i as usize)
+	}
+    }
+
+    fn union<U>(&self, other: &Series<U>) -> (NaiveDate,NaiveDate) {
+	match (self.is_empty(), other.is_empty()) {
+	    (true, _) => (other.start, other.end()),
+	    (_, true) => (self.start, self.end()),
+	    _ => (self.start.min(other.start), self.end().max(other.end()))
+	}
+    }
+
+}
+
+
+impl<T> Add for &Series<T> where T: Add<Output = T> + Copy {
+    type Output = Series<T>;
+    fn add(self, other: Self) -> Series<T> {
+	self.zip_with(other, |a,b| *a + *b)
+    }
+}
+
+impl<T> Sub for &Series<T> where T: Sub<Output = T> + Copy {
+    type Output = Series<T>;
+    fn sub(self, other: Self) -> Series<T> {
+	self.zip_with(other, |a,b| *a - *b)
+    }
+}
+
+impl Div for &Series<f64> {
+    type Output = Series<f64>;
+    fn div(self, other: Self) -> Series<f64> {
+	self.zip_with(other, |a,b| *a / *b)
+    }
+}
+
+
+impl Estimate {
+
+    /// An estimate without uncertainty.
+    pub fn exact(value: f64) -> Self {
+	Self { value, lower: value, upper: value }
+    }
+
+}
+
+
+impl fmt::Display for Correction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+	match self {
+	    Self::Negative { date, value } => write!(
+		f, "negative count {:.0} on {}", value, date.format("%Y-%m-%d")),
+	    Self::Jump { date, value, expected } => write!(
+		f, "jump to {:.0} (expected about {:.0}) on {}", value, expected, date.format("%Y-%m-%d"))
+	}
+    }
+}
+
+
+impl Kernel {
+
+    pub const ALL: [Kernel; 4] = [
+	Self::Trailing,
+	Self::Centered,
+	Self::Triangular,
+	Self::Exponential,
+    ];
+
+    pub fn name(&self) -> &'static str {
+	match self {
+	    Self::Trailing => "trailing",
+	    Self::Centered => "centered",
+	    Self::Triangular => "triangular",
+	    Self::Exponential => "exponential"
+	}
+    }
+
+    /// Qualifier for graph titles ("7-day centered average").
+    pub fn adjective(&self) -> &'static str {
+	match self {
+	    Self::Trailing => "",
+	    Self::Centered => "centered ",
+	    Self::Triangular => "triangular-weighted ",
+	    Self::Exponential => "exponentially weighted "
+	}
+    }
+
+}
+
+
+impl GrowthMethod {
+
+    pub const ALL: [GrowthMethod; 2] = [
+	Self::Ratio,
+	Self::Regression,
+    ];
+
+    pub fn name(&self) -> &'static str {
+	match self {
+	    Self::Ratio => "ratio",
+	    Self::Regression => "regression"
+	}
+    }
+
+}
+
+
+impl Tests {
+
+    /// The proportion of positive tests among those with a result.
+    pub fn positivity(&self) -> f64 {
+	self.positive / (self.positive + self.negative)
+    }
+
+}
+
+impl Add for Tests {
+    type Output = Self;
+    fn add(self, other: Self) -> Self {
+	Self {
+	    positive: self.positive + other.positive,
+	    negative: self.negative + other.negative,
+	    total: self.total + other.total
+	}
+    }
+}
+
+impl AddAssign for Tests {
+    fn add_assign(&mut self, other: Self) {
+	*self = *self + other;
+    }
+}
+
+impl Mul<f64> for Tests {
+    type Output = Self;
+    fn mul(self, n: f64) -> Self {
+	Self {
+	    positive: self.positive * n,
+	    negative: self.negative * n,
+	    total: self.total * n
+	}
+    }
+}
+
+impl Sub for Tests {
+    type Output = Self;
+    fn sub(self, other: Self) -> Self {
+	Self {
+	    positive: self.positive - other.positive,
+	    negative: self.negative - other.negative,
+	    total: self.total - other.total
+	}
+    }
+}
+
+impl Div<f64> for Tests {
+    type Output = Self;
+    fn div(self, n: f64) -> Self {
+	Self {
+	    positive: self.positive / n,
+	    negative: self.negative / n,
+	    total: self.total / n
+	}
+    }
+}
+
+
+/// Fill the gaps in a cumulative count series by linear interpolation
+/// between the surrounding known values, starting from zero. Trailing
+/// gaps remain missing.
+pub fn interpolate(series: &Series) -> Series {
+
+    let mut result = Vec::new();
+    let mut s = 0.0;
+    let mut n = 1;
+
+    for i in series.values() {
+	match i {
+	    Some(i) => {
+		for j in 1..n {
+		    result.push(Some(s + (i - s) * j as f64 / n as f64));
+		}
+		result.push(Some(*i));
+		s = *i; n = 1;
+	    }
+	    None => {
+		n += 1;
+	    }
+	}
+    }
+
+    result.resize(series.len(), None);
+    Series::new(series.start(), result)
+
+}
+
+/// Cumulative sum of a daily series. Missing days count as zero, but
+/// remain missing in the result.
+pub fn cumsum(data: &Series) -> Series {
+    let mut sum = 0.0;
+    data.map(|v| {sum += v; sum})
+}
+
+
+/// Daily differences of a cumulative series. The result is one day
+/// shorter than the input.
+pub fn daily(data: &Series) -> Series {
+    match data.is_empty() {
+	true => data.clone(),
+	false => Series::new(data.start().succ(), data.values().windows(2).map(
+	    |w| match (w[0], w[1]) {
+		(Some(a), Some(b)) => Some(b - a),
+		_ => None
+	    }
+	).collect())
+    }
+}
+
+
+/// Scale a series to counts per 100k inhabitants.
+pub fn incidence(data: &Series, population: u64) -> Series {
+    data.map(|n| *n * 100000.0 / population as f64)
+}
+
+
+/// Average daily growth factor over the last `avg` days.
+pub fn growths(data: &Series, avg: usize) -> Series {
+    let values = data.values();
+    Series::new(data.start(), (0..values.len()).map(
+	|i| match (values[i], values[i - avg.min(i)]) {
+	    (Some(a), Some(b)) => {
+		let f = (a / b).powf(1.0 / avg as f64);
+		Some(match /* !f.is_normal() || */ f == 0.0 {
+		    true => 1.0,
+		    false => f
+		})
+	    },
+	    _ => None
+	}
+    ).collect())
+}
+
+
+/// Daily growth factor over the `window` days up to each date, with
+/// its 95% confidence interval, from a Poisson log-linear regression
+/// on the daily counts (allowing for overdispersion). Negative counts
+/// are taken as zero; windows with missing days, without cases or
+/// for which the fit does not converge have no estimate.
+pub fn regression_growths(data: &Series, window: usize) -> Series<Estimate> {
+    let values = data.values();
+    Series::new(data.start(), (0..values.len()).map(
+	|i| match i + 1 < window {
+	    true => None,
+	    false => values[i+1-window..=i].iter().map(
+		|v| v.map(|v| v.max(0.0))
+	    ).collect::<Option<Vec<_>>>().and_then(|counts| poisson_slope(&counts))
+	}
+    ).collect())
+}
+
+/// A Poisson log-linear fit log(mu) = a + b x of consecutive counts,
+/// with x centered on the window.
+pub(crate) struct PoissonFit {
+    pub a: f64,
+    pub b: f64,
+    /// Covariance matrix of (a, b), scaled by the dispersion.
+    pub cov: [[f64; 2]; 2],
+    /// Pearson estimate of the overdispersion (at least one).
+    pub dispersion: f64,
+}
+
+impl PoissonFit {
+
+    /// The x value of the i'th count in a window of n.
+    pub fn x(i: f64, n: usize) -> f64 {
+	i - (n - 1) as f64 / 2.0
+    }
+
+}
+
+/// Return exp(b) of the Poisson fit of `counts`, with its confidence
+/// interval.
+fn poisson_slope(counts: &[f64]) -> Option<Estimate> {
+    poisson_fit(counts).map(|fit| {
+	let se = fit.cov[1][1].sqrt();
+	Estimate {
+	    value: fit.b.exp(),
+	    lower: (fit.b - 1.96 * se).exp(),
+	    upper: (fit.b + 1.96 * se).exp()
+	}
+    })
+}
+
+/// Fit log(mu) = a + b x by iteratively reweighted least squares.
+pub(crate) fn poisson_fit(counts: &[f64]) -> Option<PoissonFit> {
+
+    let n = counts.len();
+    let total : f64 = counts.iter().sum();
+    if n < 2 || total == 0.0 {
+	return None;
+    }
+
+    let xs = (0..n).map(|i| PoissonFit::x(i as f64, n)).collect::<Vec<_>>();
+    let mut a = (total / n as f64).ln();
+    let mut b = 0.0;
+
+    for _ in 0..50 {
+
+	let (mut s0, mut s1, mut s2, mut u0, mut u1) = (0.0, 0.0, 0.0, 0.0, 0.0);
+	for (x,y) in xs.iter().zip(counts) {
+	    let mu = (a + b * x).exp();
+	    s0 += mu; s1 += x * mu; s2 += x * x * mu;
+	    u0 += y - mu; u1 += x * (y - mu);
+	}
+
+	let det = s0 * s2 - s1 * s1;
+	if !det.is_normal() {
+	    return None;
+	}
+	let (da, db) = ((s2 * u0 - s1 * u1) / det, (s0 * u1 - s1 * u0) / det);
+	a += da; b += db;
+
+	if da.abs() < 1e-10 && db.abs() < 1e-10 {
+	    let pearson : f64 = xs.iter().zip(counts).map(|(x,y)| {
+		let mu = (a + b * x).exp();
+		(y - mu).powi(2) / mu
+	    }).sum();
+	    let dispersion = match n > 2 {
+		true => (pearson / (n - 2) as f64).max(1.0),
+		false => 1.0
+	    };
+	    return Some(PoissonFit {
+		a, b, dispersion,
+		cov: [[dispersion * s2 / det, -dispersion * s1 / det],
+		      [-dispersion * s1 / det, dispersion * s0 / det]]
+	    });
+	}
+
+    }
+
+    None
+
+}
+
+
+/// A count is a jump when it exceeds `JUMP_FACTOR` times the median
+/// of the preceding `JUMP_WINDOW` days, or `JUMP_FACTOR` times
+/// `JUMP_MIN` if that is higher.
+const JUMP_WINDOW: usize = 7;
+const JUMP_FACTOR: f64 = 5.0;
+const JUMP_MIN: f64 = 10.0;
+
+/// Detect negative counts and implausibly large single-day jumps in
+/// a daily count series, as caused by corrections to the cumulative
+/// totals. Negative counts are set to zero; with `redistribute`, they
+/// are taken from the preceding `redistribute` days, and the excess of
+/// a jump over the median of the preceding days is spread over those
+/// days, in proportion to their counts. Without, jumps are only
+/// reported.
+pub fn correct_daily(data: &Series, redistribute: Option<usize>) -> (Series, Vec<Correction>) {
+
+    let mut values = data.values().to_vec();
+    let mut corrections = Vec::new();
+
+    for (i,date) in data.dates().enumerate() {
+
+	let value = match values[i] {
+	    Some(value) => value,
+	    None => continue
+	};
+
+	let mut recent = values[i.saturating_sub(JUMP_WINDOW)..i].iter()
+	    .flatten().cloned().collect::<Vec<_>>();
+	let expected = match recent.len() == JUMP_WINDOW {
+	    true => median(&mut recent),
+	    false => None
+	};
+
+	if value < 0.0 {
+	    corrections.push(Correction::Negative { date, value });
+	    values[i] = Some(0.0);
+	    if let Some(days) = redistribute {
+		spread(&mut values[i.saturating_sub(days)..i], value);
+	    }
+	} else if let Some(expected) = expected {
+	    if value > JUMP_FACTOR * expected.max(JUMP_MIN) {
+		corrections.push(Correction::Jump { date, value, expected });
+		if let Some(days) = redistribute {
+		    values[i] = Some(expected);
+		    spread(&mut values[i.saturating_sub(days)..i], value - expected);
+		}
+	    }
+	}
+
+    }
+
+    (Series::new(data.start(), values), corrections)
+
+}
+
+/// Add `amount` to the present `values` in proportion to their size
+/// (evenly if they are all zero), without making any negative.
+fn spread(values: &mut [Option<f64>], amount: f64) {
+    let total : f64 = values.iter().flatten().sum();
+    let n = values.iter().flatten().count();
+    for v in values.iter_mut().flatten() {
+	*v = match total > 0.0 {
+	    true => (*v + amount * *v / total).max(0.0),
+	    false => (*v + amount / n as f64).max(0.0)
+	};
+    }
+}
+
+
+/// Days on which the value exceeds the median of the `window` days
+/// centered on it by more than `threshold` robust z-scores, i.e. in
+/// units of the scaled median absolute deviation over that window (of
+/// at least one). Only upward deviations, such as batch uploads of a
+/// backlog, are flagged.
+pub fn anomalies(data: &Series, window: usize, threshold: f64) -> Vec<NaiveDate> {
+    let values = data.values();
+    data.dates().enumerate().filter_map(|(i,date)| {
+	let value = values[i]?;
+	let mut recent = values[i.saturating_sub(window / 2)..values.len().min(i + window / 2 + 1)]
+	    .iter().flatten().cloned().collect::<Vec<_>>();
+	let median = median(&mut recent)?;
+	let mad = self::median(&mut recent.iter().map(|v| (v - median).abs()).collect::<Vec<_>>())?;
+	match 0.6745 * (value - median) / mad.max(1.0) > threshold {
+	    true => Some(date),
+	    false => None
+	}
+    }).collect()
+}
+
+/// Days on which the value is below `fraction` of the median of the
+/// `window` days centered on it, such as days on which fewer
+/// hospitals reported.
+pub fn drops(data: &Series, window: usize, fraction: f64) -> Vec<NaiveDate> {
+    let values = data.values();
+    data.dates().enumerate().filter_map(|(i,date)| {
+	let value = values[i]?;
+	let median = median(&mut values[i.saturating_sub(window / 2)..values.len().min(i + window / 2 + 1)]
+			    .iter().flatten().cloned().collect::<Vec<_>>())?;
+	match value < fraction * median {
+	    true => Some(date),
+	    false => None
+	}
+    }).collect()
+}
+
+/// Replace the values on `dates` by the median of the `window` days
+/// centered on them.
+pub fn replace_by_median(data: &Series, window: usize, dates: &[NaiveDate]) -> Series {
+    let values = data.values();
+    Series::new(data.start(), data.dates().enumerate().map(
+	|(i,date)| match dates.contains(&date) {
+	    false => values[i],
+	    true => median(&mut values[i.saturating_sub(window / 2)..values.len().min(i + window / 2 + 1)]
+			   .iter().flatten().cloned().collect::<Vec<_>>())
+	}
+    ).collect())
+}
+
+fn median(values: &mut [f64]) -> Option<f64> {
+    values.sort_by(|a,b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    match values.len() {
+	0 => None,
+	n if n % 2 == 0 => Some((values[n/2 - 1] + values[n/2]) / 2.0),
+	n => Some(values[n/2])
+    }
+}
+
+
+/// Doubling time in days for daily growth factors above one, and
+/// (negative) halving time for factors below one, capped at `cap`
+/// days either way for near-flat periods. Factors that are not
+/// positive (no cases left) or not numbers have no doubling time and
+/// are missing.
+pub fn doubling_times(growths: &Series, cap: f64) -> Series {
+    Series::new(growths.start(), growths.values().iter().map(
+	|f| f.filter(|f| *f > 0.0).map(|f| match 2f64.ln() / f.ln() {
+	    t if t > cap => cap,
+	    t if t < -cap => -cap,
+	    t => t
+	})
+    ).collect())
+}
+
+
+/// Multiplicative day-of-week factors (Monday first) of a daily
+/// series, estimated over its last `weeks` weeks as the mean ratio of
+/// each day to the centered 7-day average around it. The factors
+/// average to one; weekdays without data get a factor of one.
+pub fn weekday_factors(data: &Series, weeks: usize) -> [f64; 7] {
+
+    let values = data.values();
+    let mut ratios = [(0.0, 0); 7];
+
+    let end = values.len().saturating_sub(3);
+    for i in end.saturating_sub(weeks * 7).max(3)..end {
+	let date = data.start() + Duration::days(i as i64);
+	let week = values[i-3..=i+3].iter().cloned().collect::<Option<Vec<f64>>>();
+	if let (Some(value), Some(week)) = (values[i], week) {
+	    let mean = week.iter().sum::<f64>() / 7.0;
+	    if mean > 0.0 {
+		let r = &mut ratios[date.weekday().num_days_from_monday() as usize];
+		r.0 += value / mean;
+		r.1 += 1;
+	    }
+	}
+    }
+
+    let mut factors = [1.0; 7];
+    for (factor,(sum,n)) in factors.iter_mut().zip(ratios.iter()) {
+	if *n > 0 && *sum > 0.0 {
+	    *factor = sum / *n as f64;
+	}
+    }
+    let mean = factors.iter().sum::<f64>() / 7.0;
+    for factor in factors.iter_mut() {
+	*factor /= mean;
+    }
+    factors
+
+}
+
+/// Daily series with the day-of-week effect removed, using the
+/// factors estimated over its last `weeks` weeks.
+pub fn adjust_weekdays(data: &Series, weeks: usize) -> Series {
+    let factors = weekday_factors(data, weeks);
+    Series::new(data.start(), data.dates().zip(data.values()).map(
+	|(date,v)| v.map(|v| v / factors[date.weekday().num_days_from_monday() as usize])
+    ).collect())
+}
+
+
+/// Trailing moving average over `avg` days, of the values present
+/// in each window.
+pub fn average<T>(data: &Series<T>, avg: usize) -> Series<T>
+where T: Add<Output = T> + Sub<Output = T> + Div<f64,Output = T> + Default + Copy {
+    trailing(data, avg, |sum,n| sum / n as f64)
+}
+
+
+/// Trailing moving sum over `len` days.
+pub fn sum(data: &Series, len: usize) -> Series {
+    trailing(data, len, |sum,_| sum)
+}
+
+
+/// Moving average over `len` days, weighted by `kernel`. Windows
+/// extending beyond the series, or with missing days, average over
+/// the days present.
+pub fn smooth<T>(data: &Series<T>, len: usize, kernel: Kernel) -> Series<T>
+where T: Add<Output = T> + Sub<Output = T> + Mul<f64,Output = T> + Div<f64,Output = T>
+    + Default + Copy {
+    match kernel {
+	Kernel::Trailing => average(data, len),
+	Kernel::Centered => weighted(data, (len - 1) / 2, &vec![1.0; len]),
+	Kernel::Triangular => weighted(data, (len - 1) / 2, &(0..len).map(
+	    |j| (j.min(len - 1 - j) + 1) as f64
+	).collect::<Vec<_>>()),
+	Kernel::Exponential => exponential(data, 2.0 / (len as f64 + 1.0))
+    }
+}
+
+
+/// Moving sum over `len` days, weighted by `kernel`: the smoothed
+/// average times `len`.
+pub fn smooth_sum(data: &Series, len: usize, kernel: Kernel) -> Series {
+    match kernel {
+	Kernel::Trailing => sum(data, len),
+	kernel => smooth(data, len, kernel).map(|v| v * len as f64)
+    }
+}
+
+
+/// Sum several series by date, over all their dates. Missing values
+/// are skipped; the sum is missing only where all values are. None
+/// if there are no values at all.
+pub fn sum_series(data: &[&Series]) -> Option<Series> {
+    let mut result = BTreeMap::new();
+    for series in data {
+	for (date,val) in series.present() {
+	    *result.entry(date).or_insert(0.0) += *val;
+	}
+    }
+    Some(Series::from_map(*result.keys().next()?, *result.keys().last()?, &result))
+}
+
+
+fn trailing<T,F>(data: &Series<T>, len: usize, f: F) -> Series<T>
+where T: Add<Output = T> + Sub<Output = T> + Default + Copy,
+      F: Fn(T,usize) -> T {
+    let values = data.values();
+    let mut sum = T::default();
+    let mut n = 0;
+    Series::new(data.start(), (0..values.len()).map(|i| {
+	let old = match i >= len {
+	    true => values[i-len],
+	    false => None
+	};
+	sum = sum + (values[i].unwrap_or_default() - old.unwrap_or_default());
+	n = n + values[i].is_some() as usize - old.is_some() as usize;
+	match n {
+	    0 => None,
+	    n => Some(f(sum, n))
+	}
+    }).collect())
+}
+
+/// Weighted average of the window starting `back` days before each
+/// date, over the days present.
+fn weighted<T>(data: &Series<T>, back: usize, weights: &[f64]) -> Series<T>
+where T: Add<Output = T> + Mul<f64,Output = T> + Div<f64,Output = T> + Default + Copy {
+    let values = data.values();
+    Series::new(data.start(), (0..values.len()).map(|i| {
+	let mut sum = T::default();
+	let mut total = 0.0;
+	for (j,w) in weights.iter().enumerate() {
+	    if let Some(v) = (i + j).checked_sub(back).and_then(|k| values.get(k)).and_then(|v| *v) {
+		sum = sum + v * *w;
+		total += w;
+	    }
+	}
+	match total > 0.0 {
+	    true => Some(sum / total),
+	    false => None
+	}
+    }).collect())
+}
+
+/// Exponentially weighted moving average with smoothing factor
+/// `alpha`; missing days are skipped and remain missing.
+fn exponential<T>(data: &Series<T>, alpha: f64) -> Series<T>
+where T: Add<Output = T> + Mul<f64,Output = T> + Copy {
+    let mut state : Option<T> = None;
+    data.map(|v| {
+	let next = match state {
+	    None => *v,
+	    Some(s) => s * (1.0 - alpha) + *v * alpha
+	};
+	state = Some(next);
+	next
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn date(day: u32) -> NaiveDate {
+	NaiveDate::from_ymd(2020, 10, day)
+    }
+
+    fn series(values: &[f64]) -> Series {
+	Series::from_values(date(1), values.iter().cloned())
+    }
+
+    #[test]
+    fn corrections() {
+	let data = series(&[10.0, 12.0, 11.0, 9.0, 10.0, 11.0, 12.0, 100.0, -20.0, 10.0, f64::NAN, 11.0]);
+	let expected = vec![
+	    Correction::Jump { date: date(8), value: 100.0, expected: 11.0 },
+	    Correction::Negative { date: date(9), value: -20.0 }
+	];
+
+	let (corrected, corrections) = correct_daily(&data, None);
+	assert_eq!(corrections, expected);
+	assert_eq!(corrected.get(date(8)), Some(&100.0));
+	assert_eq!(corrected.get(date(9)), Some(&0.0));
+	assert!(corrected.get(date(11)).unwrap().is_nan());
+
+	let (corrected, corrections) = correct_daily(&data, Some(3));
+	assert_eq!(corrections, expected);
+	assert_eq!(corrected.get(date(9)), Some(&0.0));
+	assert!(corrected.present().all(|(_,v)| v.is_nan() || (*v >= 0.0 && *v < 50.0)));
+	let total = |series: &Series| series.present().map(|(_,v)| *v).filter(|v| !v.is_nan()).sum::<f64>();
+	assert!((total(&corrected) - total(&data)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn poisson_regression() {
+	let counts = (0..14).map(|i| 100.0 * (0.1 * i as f64).exp()).collect::<Vec<_>>();
+	let data = series(&counts);
+	let fit = poisson_fit(&counts).unwrap();
+	assert!((fit.b - 0.1).abs() < 1e-9);
+	assert!((fit.a - (100.0f64.ln() + 0.1 * 6.5)).abs() < 1e-9);
+	assert_eq!(fit.dispersion, 1.0);
+
+	let growths = regression_growths(&data, 7);
+	assert_eq!(growths.get(date(6)), None);
+	let estimate = growths.get(date(14)).unwrap();
+	assert!((estimate.value - 0.1f64.exp()).abs() < 1e-9);
+	assert!(estimate.lower < estimate.value && estimate.upper > estimate.value);
+
+	// Zero counts within the window are fitted, windows without any
+	// cases are not.
+	assert!(poisson_fit(&[0.0, 1.0, 0.0, 2.0, 3.0, 5.0, 8.0]).unwrap().b > 0.0);
+	assert!(poisson_fit(&[0.0; 7]).is_none());
+	assert!(poisson_fit(&[5.0]).is_none());
+	assert!(regression_growths(&series(&[1.0, 2.0, 4.0]), 7).present().next().is_none());
+    }
+
+    #[test]
+    fn anomalous_days() {
+	let mut values = (0..30).map(|i| 100.0 + (i % 3) as f64 * 5.0).collect::<Vec<_>>();
+	values[20] = 300.0;
+	values[10] = 50.0;
+	let data = series(&values);
+	assert_eq!(anomalies(&data, 15, 3.5), vec![date(21)]);
+	assert_eq!(drops(&data, 15, 0.9), vec![date(11)]);
+	let replaced = replace_by_median(&data, 15, &[date(21)]);
+	assert_eq!(replaced.get(date(21)), Some(&105.0));
+	assert_eq!(replaced.get(date(20)), data.get(date(20)));
+
+	// Without any spread (a MAD of zero) only clear outliers are
+	// flagged.
+	assert!(anomalies(&series(&[10.0; 30]), 15, 3.5).is_empty());
+	let mut values = vec![10.0; 30];
+	values[15] = 11.0;
+	values[20] = 20.0;
+	assert_eq!(anomalies(&series(&values), 15, 3.5), vec![date(21)]);
+    }
+
+    #[test]
+    fn kernels() {
+	let close = |a: &[f64], b: &[f64]| a.len() == b.len()
+	    && a.iter().zip(b).all(|(a,b)| (a - b).abs() < 1e-9);
+
+	for kernel in &Kernel::ALL {
+	    assert!(smooth(&series(&[3.0; 20]), 7, *kernel).present().all(|(_,v)| (v - 3.0).abs() < 1e-12));
+	}
+
+	// The response to an impulse gives the weights: they sum to one,
+	// follow the impulse for the trailing kernels and are symmetric
+	// around it for the centered ones.
+	let mut values = vec![0.0; 61];
+	values[30] = 1.0;
+	let impulse = series(&values);
+	let weights = |kernel| smooth(&impulse, 7, kernel).values().iter().map(|v| v.unwrap()).collect::<Vec<_>>();
+	for kernel in &Kernel::ALL {
+	    let weights = weights(*kernel);
+	    assert!((weights.iter().sum::<f64>() - 1.0).abs() < 1e-3, "{}", kernel.name());
+	}
+	assert!(close(&weights(Kernel::Trailing)[29..38], &[0.0, 1.0/7.0, 1.0/7.0, 1.0/7.0, 1.0/7.0,
+							   1.0/7.0, 1.0/7.0, 1.0/7.0, 0.0]));
+	assert!(close(&weights(Kernel::Centered)[26..35], &[0.0, 1.0/7.0, 1.0/7.0, 1.0/7.0, 1.0/7.0,
+							   1.0/7.0, 1.0/7.0, 1.0/7.0, 0.0]));
+	assert!(close(&weights(Kernel::Triangular)[26..35], &[0.0, 1.0/16.0, 2.0/16.0, 3.0/16.0, 4.0/16.0,
+							     3.0/16.0, 2.0/16.0, 1.0/16.0, 0.0]));
+	let exponential = weights(Kernel::Exponential);
+	assert_eq!(exponential[29], 0.0);
+	assert!((exponential[30] - 0.25).abs() < 1e-12 && (exponential[31] - 0.1875).abs() < 1e-12);
+
+	// At the edges and around missing days, the windows average
+	// over the days present.
+	let values = |series: Series| series.values().to_vec();
+	let data = series(&[1.0, 2.0, 3.0, 4.0, 5.0]);
+	assert_eq!(values(smooth(&data, 3, Kernel::Centered)), vec![Some(1.5), Some(2.0), Some(3.0), Some(4.0), Some(4.5)]);
+	assert_eq!(values(smooth(&data, 3, Kernel::Trailing)), vec![Some(1.0), Some(1.5), Some(2.0), Some(3.0), Some(4.0)]);
+	assert_eq!(smooth(&data, 3, Kernel::Triangular).get(date(1)), Some(&(4.0 / 3.0)));
+	let data = Series::new(date(1), vec![Some(1.0), None, Some(3.0), Some(4.0)]);
+	assert_eq!(values(smooth(&data, 2, Kernel::Trailing)), vec![Some(1.0), Some(1.0), Some(3.0), Some(3.5)]);
+	assert_eq!(values(smooth(&data, 3, Kernel::Centered)), vec![Some(1.0), Some(2.0), Some(3.5), Some(3.5)]);
+	assert_eq!(values(smooth(&data, 3, Kernel::Exponential)), vec![Some(1.0), None, Some(2.0), Some(3.0)]);
+    }
+
+    #[test]
+    fn alignment() {
+	let a = series(&[1.0, 2.0, 3.0]);
+	let b = Series::new(date(3), vec![Some(10.0), None, Some(30.0)]);
+
+	let reindexed = a.reindex(date(2), date(5));
+	assert_eq!(reindexed.start(), date(2));
+	assert_eq!(reindexed.values(), &[Some(2.0), Some(3.0), None, None]);
+
+	let (a2, b2) = a.align(&b);
+	assert_eq!((a2.start(), a2.end()), (date(1), date(5)));
+	assert_eq!((b2.start(), b2.end()), (date(1), date(5)));
+	assert_eq!(a2.values(), &[Some(1.0), Some(2.0), Some(3.0), None, None]);
+	assert_eq!(b2.values(), &[None, None, Some(10.0), None, Some(30.0)]);
+
+	let lagged = a.lag(2);
+	assert_eq!(lagged.get(date(3)), Some(&1.0));
+	assert_eq!(lagged.get(date(2)), None);
+	assert_eq!(a.lag(-1).get(date(1)), Some(&2.0));
+
+	assert_eq!((&a + &b).values(), &[None, None, Some(13.0), None, None]);
+	assert_eq!((&b - &a).values(), &[None, None, Some(7.0), None, None]);
+	assert_eq!((&b / &a).get(date(3)), Some(&(10.0 / 3.0)));
+	assert_eq!((&a + &Series::new(date(9), vec![])).values(), &[None, None, None]);
+    }
+
+    #[test]
+    fn sums() {
+	let a = series(&[1.0, 2.0, 3.0]);
+	let b = Series::new(date(3), vec![Some(10.0), None, Some(30.0)]);
+	let sum = sum_series(&[&a, &b]).unwrap();
+	assert_eq!(sum.start(), date(1));
+	assert_eq!(sum.values(), &[Some(1.0), Some(2.0), Some(13.0), None, Some(30.0)]);
+	assert_eq!(sum_series(&[]), None);
+	assert_eq!(sum_series(&[&Series::new(date(1), vec![None, None])]), None);
+    }
+
+    #[test]
+    fn doubling() {
+	let growths = Series::new(date(1), vec![
+	    Some(2.0), Some(0.5), Some(1.0), Some(1.0001), Some(0.0), Some(-0.5), Some(f64::NAN), None
+	]);
+	let times = doubling_times(&growths, 100.0);
+	assert_eq!(times.start(), date(1));
+	assert_eq!(times.values(), &[
+	    Some(1.0), Some(-1.0), Some(100.0), Some(100.0), None, None, None, None
+	]);
+    }
+
+}
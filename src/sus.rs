@@ -1,12 +1,19 @@
+//! Brazilian e-SUS test notifications.
+
+use std::{fs,io};
+use std::fs::File;
+use std::path::Path;
 use std::collections::BTreeMap;
-use chrono::{NaiveDateTime,Utc};
-use serde::Deserialize;
+use chrono::{NaiveDate,NaiveDateTime,DateTime,Local,Duration,Utc};
+use serde::{Serialize,Deserialize};
 use serde_json::Value;
 use serde_json::json;
+use unidecode::unidecode;
 
 use super::graph::TestsData;
+use super::series::{Series,Tests};
 use super::error::{Result,Error};
-use super::NaiveDateRange;
+use super::cache::CacheMode;
 
 
 #[derive(Deserialize)]
@@ -72,7 +79,82 @@ enum TestResult {
 }
 
 
-pub fn tests(name: &str, filter: Option<Value>, indices: &str) -> Result<TestsData> {
+#[derive(Serialize,Deserialize)]
+struct CachedTests {
+    start: String,
+    tests: Vec<(f64,f64,f64)>
+}
+
+
+/// Whether a query covers a whole state (the notification indices of
+/// the state) or a single municipality in it.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Level {
+    Estado,
+    Municipio,
+}
+
+impl Level {
+
+    /// The name of the level, as used in the cache paths.
+    pub fn name(&self) -> &'static str {
+	match self {
+	    Self::Estado => "estado",
+	    Self::Municipio => "municipio"
+	}
+    }
+
+    /// The Elasticsearch filter selecting `name` at this level.
+    fn filter(&self, name: &str) -> Option<Value> {
+	match self {
+	    Self::Estado => None,
+	    Self::Municipio => Some(json!({"term": {"municipio": name}}))
+	}
+    }
+
+}
+
+
+/// Daily test results of the state or municipality `name` from the
+/// e-SUS notification `indices` of its state. States and
+/// municipalities are cached separately, since their names overlap.
+pub fn tests(cache_path: &Path, mode: CacheMode, level: Level, name: &str,
+	     indices: &str) -> Result<TestsData> {
+
+    let cache_path = cache_path.join("sus").join(indices).join(level.name());
+    let filename = format!("{}.json", unidecode(name));
+    let cache_file = cache_path.join(&filename);
+    let cache_name = format!("sus/{}/{}/{}", indices, level.name(), filename);
+
+    if cache_file.exists() {
+	let modified : DateTime<Local> = fs::metadata(&cache_file)?.modified()?.into();
+	if mode.use_cached(Local::now() - modified < Duration::hours(12)) {
+	    mode.report(&cache_name, &cache_file)?;
+	    let cached : CachedTests = serde_json::from_reader(io::BufReader::new(File::open(&cache_file)?))?;
+	    return Ok(Series::from_values(
+		NaiveDate::parse_from_str(&cached.start, "%Y-%m-%d")?,
+		cached.tests.into_iter().map(|(positive,negative,total)| Tests {
+		    positive, negative, total
+		})));
+	}
+    }
+
+    mode.download(&cache_name)?;
+    let data = query_tests(name, level.filter(name), indices)?;
+
+    fs::create_dir_all(&cache_path)?;
+    serde_json::to_writer(io::BufWriter::new(File::create(cache_file)?), &CachedTests {
+	start: format!("{}", data.start().format("%Y-%m-%d")),
+	tests: data.fill(Tests::default()).present().map(
+	    |(_,tests)| (tests.positive, tests.negative, tests.total)
+	).collect()
+    })?;
+    Ok(data)
+
+}
+
+
+fn query_tests(name: &str, filter: Option<Value>, indices: &str) -> Result<TestsData> {
 
     println!("Querying test data for {}...", name);
 
@@ -108,31 +190,28 @@ pub fn tests(name: &str, filter: Option<Value>, indices: &str) -> Result<TestsDa
 	    let mut tests = BTreeMap::new();
 	    
 	    for date_bucket in result.sub.aggregations.date.buckets {
-		let (pos,neg,all) = tests.entry(NaiveDateTime::from_timestamp(date_bucket.key / 1000, 0).date())
-		    .or_insert((0.0,0.0,0.0));
+		let day : &mut Tests = tests.entry(NaiveDateTime::from_timestamp(date_bucket.key / 1000, 0).date())
+		    .or_default();
 		for result_bucket in date_bucket.sub.resultados.buckets {
 		    match result_bucket.key {
 			TestResult::Confirmado => {
-			    *pos += result_bucket.doc_count as f64;
-			    *all += result_bucket.doc_count as f64;
+			    day.positive += result_bucket.doc_count as f64;
+			    day.total += result_bucket.doc_count as f64;
 			},
 			TestResult::Descartado => {
-			    *neg += result_bucket.doc_count as f64;
-			    *all += result_bucket.doc_count as f64;
+			    day.negative += result_bucket.doc_count as f64;
+			    day.total += result_bucket.doc_count as f64;
 			},
 			TestResult::Unexpected => {
-			    *all += result_bucket.doc_count as f64;
+			    day.total += result_bucket.doc_count as f64;
 			}
 		    }
 		}
 	    }
 
-	    let date_range = NaiveDateRange(*tests.keys().min().ok_or(Error::MissingData)?,
-					    Some(*tests.keys().max().ok_or(Error::MissingData)?));
-
-	    Ok(date_range.map(
-		|date| (date.clone(), tests.remove(&date).unwrap_or((0.0,0.0,0.0)))
-	    ).collect())
+	    Ok(Series::from_map(*tests.keys().next().ok_or(Error::MissingData)?,
+				*tests.keys().last().ok_or(Error::MissingData)?,
+				&tests).fill(Tests::default()))
 
 	},
 	ESResult::Unexpected(v) => Err(Error::ESQueryFailed(format!("unexpected result: {:?}", v)))
@@ -220,3 +299,30 @@ pub fn tests(name: &str, filter: Option<Value>, indices: &str) -> Result<TestsDa
         },
         {
 */
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn separate_caches() {
+	let cache_path = std::env::temp_dir().join(format!("covid19-sus-{}", std::process::id()));
+	let name = "São Paulo";
+	for (level,positive) in &[(Level::Estado, 100.0), (Level::Municipio, 10.0)] {
+	    let dir = cache_path.join("sus").join("sp").join(level.name());
+	    fs::create_dir_all(&dir).unwrap();
+	    serde_json::to_writer(File::create(dir.join("Sao Paulo.json")).unwrap(), &CachedTests {
+		start: "2020-10-01".to_string(),
+		tests: vec![(*positive, 0.0, *positive)]
+	    }).unwrap();
+	}
+	let estado = tests(&cache_path, CacheMode::Offline, Level::Estado, name, "sp").unwrap();
+	let municipio = tests(&cache_path, CacheMode::Offline, Level::Municipio, name, "sp").unwrap();
+	assert_eq!(estado.last().unwrap().1.positive, 100.0);
+	assert_eq!(municipio.last().unwrap().1.positive, 10.0);
+	fs::remove_dir_all(&cache_path).unwrap();
+    }
+
+}
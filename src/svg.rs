@@ -0,0 +1,684 @@
+//! Static rendering of the graphs as SVG (and, with the `png`
+//! feature, PNG) images, for use where the Vega scripts cannot run,
+//! such as mails and PDF reports.
+
+use std::fs;
+use std::fmt::Write;
+use std::path::Path;
+use std::collections::HashMap;
+
+use chrono::{Datelike,Duration};
+use chrono::naive::NaiveDate;
+use serde_json::Value;
+
+use super::error::{Result,Error};
+use super::graph::{CasesData,TestsData,WeeklyData,Refs,Overlays,Image};
+use super::series::{Series,Estimate,Tests};
+
+
+const WIDTH: f64 = 800.0;
+const HEIGHT: f64 = 500.0;
+const LEFT: f64 = 70.0;
+const RIGHT: f64 = 170.0;
+const TOP: f64 = 40.0;
+const BOTTOM: f64 = 40.0;
+
+/// The default Vega-Lite color scheme (tableau10).
+const COLORS: [&str; 10] = [
+    "#4c78a8", "#f58518", "#e45756", "#72b7b2", "#54a24b",
+    "#eeca3b", "#b279a2", "#ff9da6", "#9d755d", "#bab0ac"
+];
+
+/// The ColorBrewer YlOrRd ramp of the "yelloworangered" scheme.
+const YLORRD: [(u8,u8,u8); 9] = [
+    (0xff, 0xff, 0xcc), (0xff, 0xed, 0xa0), (0xfe, 0xd9, 0x76),
+    (0xfe, 0xb2, 0x4c), (0xfd, 0x8d, 0x3c), (0xfc, 0x4e, 0x2a),
+    (0xe3, 0x1a, 0x1c), (0xbd, 0x00, 0x26), (0x80, 0x00, 0x26)
+];
+
+/// Fill of the areas without a value.
+const NO_DATA: &str = "#eeeeee";
+
+
+/// Write `svg` as `name.svg` and/or `name.png` in `graph_path`.
+pub fn write(graph_path: &Path, name: &str, svg: &str, images: &[Image]) -> Result<()> {
+    for image in images {
+	match image {
+	    Image::Svg => fs::write(graph_path.join(format!("{}.svg", name)), svg)?,
+	    Image::Png => png(svg, &graph_path.join(format!("{}.png", name)))?
+	}
+    }
+    Ok(())
+}
+
+#[cfg(feature = "png")]
+fn png(svg: &str, path: &Path) -> Result<()> {
+    use std::sync::{Arc,OnceLock};
+    use resvg::{usvg,tiny_skia};
+    static FONTS: OnceLock<Arc<usvg::fontdb::Database>> = OnceLock::new();
+    let options = usvg::Options {
+	fontdb: FONTS.get_or_init(|| {
+	    let mut fonts = usvg::fontdb::Database::new();
+	    fonts.load_system_fonts();
+	    Arc::new(fonts)
+	}).clone(),
+	..usvg::Options::default()
+    };
+    let tree = usvg::Tree::from_str(svg, &options).map_err(|err| Error::Render(err.to_string()))?;
+    let size = tree.size().to_int_size();
+    let mut pixmap = tiny_skia::Pixmap::new(size.width(), size.height())
+	.ok_or_else(|| Error::Render("empty image".to_string()))?;
+    resvg::render(&tree, tiny_skia::Transform::default(), &mut pixmap.as_mut());
+    pixmap.save_png(path).map_err(|err| Error::Render(err.to_string()))
+}
+
+#[cfg(not(feature = "png"))]
+fn png(_svg: &str, _path: &Path) -> Result<()> {
+    Err(Error::Render("built without the png feature".to_string()))
+}
+
+
+/// A line graph of `data` by region, with the `refs` and `overlays`
+/// drawn as in the HTML page. `scale` is the Vega-Lite scale of the
+/// y axis; only its type ("log") and domain are used. None if there
+/// is nothing to draw.
+pub fn lines(title: &str, ytitle: &str, scale: &Value, refs: &Refs,
+	     data: &CasesData, overlays: &Overlays) -> Option<String> {
+
+    let dates = data.iter().flat_map(|(_,series)| series.present().map(|(date,_)| date))
+	.chain(overlays.forecast.iter().flat_map(|(_,series)| series.present().map(|(date,_)| date)))
+	.collect::<Vec<_>>();
+    let values = data.iter().flat_map(|(_,series)| series.present().map(|(_,v)| *v))
+	.chain(estimates(&overlays.bands)).chain(estimates(&overlays.forecast))
+	.chain(refs.iter().map(|(_,r)| *r))
+	.collect::<Vec<_>>();
+
+    let x = TimeAxis::new(*dates.iter().min()?, *dates.iter().max()?);
+    let y = Axis::from_scale(scale, &values);
+    let mut svg = Canvas::new(title);
+
+    for (i,(_,series)) in overlays.bands.iter().enumerate() {
+	let color = color_of(data, &overlays.bands[i].0);
+	svg.band(&x, &y, series, color, 0.2);
+    }
+    for (region,series) in &overlays.forecast {
+	let color = color_of(data, region);
+	svg.band(&x, &y, series, color, 0.15);
+	svg.line(&x, &y, &series.map(|f| f.value), color, Some("4,4"));
+    }
+    for (i,(_,series)) in data.iter().enumerate() {
+	svg.line(&x, &y, series, COLORS[i % COLORS.len()], None);
+    }
+    for (region,dates) in &overlays.anomalies {
+	if let Some((_,series)) = data.iter().find(|(r,_)| r == region) {
+	    for date in dates {
+		if let Some(value) = series.get(*date).filter(|v| y.contains(**v)) {
+		    svg.diamond(x.map(*date), y.map(*value), color_of(data, region));
+		}
+	    }
+	}
+    }
+    svg.refs(&y, refs);
+    svg.x_axis(&x);
+    svg.y_axis(&y, ytitle, "black", false);
+    svg.legend(&data.iter().enumerate().map(
+	|(i,(region,_))| (region.as_str(), COLORS[i % COLORS.len()])
+    ).collect::<Vec<_>>());
+
+    Some(svg.finish())
+
+}
+
+/// The positivity ratio (left axis, red) and number of tests (right
+/// axis, blue) of a region, with the positivity `refs`.
+pub fn tests(title: &str, data: &TestsData, refs: &Refs) -> Option<String> {
+
+    let results = |f: fn(&Tests) -> f64| Series::new(data.start(), data.values().iter().map(
+	|tests| tests.filter(|t| t.positive + t.negative != 0.0).as_ref().map(f)
+    ).collect());
+    let positivity = results(|tests| 1f64.min(tests.positivity()));
+    let totals = results(|tests| tests.total);
+    let start = positivity.present().next()?.0;
+    let end = positivity.last()?.0;
+
+    let x = TimeAxis::new(start, end);
+    let y = Axis::linear(0.0, 1.0, false);
+    let y2 = Axis::linear(0.0, totals.present().map(|(_,t)| *t).fold(0.0, f64::max), true);
+    let mut svg = Canvas::new(title);
+
+    svg.line(&x, &y, &positivity, "red", None);
+    svg.line(&x, &y2, &totals, "blue", None);
+    svg.refs(&y, refs);
+    svg.x_axis(&x);
+    svg.y_axis(&y, "Proportion of positive tests", "red", false);
+    svg.y_axis(&y2, "Total number of tests", "blue", true);
+
+    Some(svg.finish())
+
+}
+
+/// The weekly values of `data` as colored cells, by week (x) and row
+/// (y, in descending order from the top as in the HTML page).
+pub fn heatmap(title: &str, ytitle: &str, value: &str, data: &WeeklyData) -> Option<String> {
+
+    let mut weeks = data.iter().flat_map(|(_,weeks)| weeks.iter().map(|(monday,_)| *monday))
+	.collect::<Vec<_>>();
+    weeks.sort();
+    weeks.dedup();
+    let mut rows = data.iter().map(|(row,_)| row.as_str()).collect::<Vec<_>>();
+    rows.sort_by(|a,b| b.cmp(a));
+    let max = data.iter().flat_map(|(_,weeks)| weeks.iter().map(|(_,val)| *val))
+	.filter(|val| val.is_finite()).fold(0.0, f64::max);
+    let color = |val: f64| ramp(match max > 0.0 { true => val / max, false => 0.0 });
+    if weeks.is_empty() {
+	return None;
+    }
+
+    let width = (WIDTH - LEFT - RIGHT) / weeks.len() as f64;
+    let height = (HEIGHT - TOP - BOTTOM) / rows.len() as f64;
+    let mut svg = Canvas::new(title);
+
+    for (i,(row,values)) in data.iter().enumerate() {
+	let y = TOP + height * rows.iter().position(|r| r == row).unwrap_or(i) as f64;
+	for (monday,val) in values.iter().filter(|(_,val)| val.is_finite()) {
+	    if let Ok(week) = weeks.binary_search(monday) {
+		svg.rect(LEFT + width * week as f64, y, width, height, &color(*val));
+	    }
+	}
+    }
+    svg.categories(&rows, ytitle);
+    let step = weeks.len() / 8 + 1;
+    for (week,monday) in weeks.iter().enumerate().step_by(step) {
+	svg.text(LEFT + width * (week as f64 + 0.5), HEIGHT - BOTTOM + 16.0, "middle",
+		 &format!("{}", monday.format("%G-W%V")));
+    }
+    svg.text((LEFT + WIDTH - RIGHT) / 2.0, HEIGHT - 6.0, "middle", "Week");
+    svg.swatches(value, &(0..5).map(|i| max * i as f64 / 4.0).map(
+	|val| (label(val), color(val))
+    ).collect::<Vec<_>>());
+
+    Some(svg.finish())
+
+}
+
+/// The values by age group (y, in descending order from the top) and
+/// sex as a population pyramid, the first sex extending to the left.
+pub fn pyramid(title: &str, data: &[(&str,&str,f64)]) -> Option<String> {
+
+    let data = data.iter().filter(|(_,_,val)| val.is_finite()).collect::<Vec<_>>();
+    let mut ages = data.iter().map(|(age,_,_)| *age).collect::<Vec<_>>();
+    ages.sort_by(|a,b| b.cmp(a));
+    ages.dedup();
+    let mut sexes = data.iter().map(|(_,sex,_)| *sex).collect::<Vec<_>>();
+    sexes.sort();
+    sexes.dedup();
+    if data.is_empty() {
+	return None;
+    }
+
+    let step = Axis::step(0.0, data.iter().map(|(_,_,val)| *val).fold(0.0, f64::max).max(1.0));
+    let max = (data.iter().map(|(_,_,val)| *val).fold(0.0, f64::max) / step).ceil().max(1.0) * step;
+    let center = (LEFT + WIDTH - RIGHT) / 2.0;
+    let x = |val: f64| center + (WIDTH - LEFT - RIGHT) / 2.0 * val / max;
+    let height = (HEIGHT - TOP - BOTTOM) / ages.len() as f64;
+    let mut svg = Canvas::new(title);
+
+    let ticks = (max / step).round() as i64;
+    for tick in (-ticks..=ticks).map(|i| i as f64 * step) {
+	let _ = write!(svg.svg, "<line x1=\"{x:.1}\" x2=\"{x:.1}\" y1=\"{}\" y2=\"{}\" stroke=\"#ddd\"/>",
+		       TOP, HEIGHT - BOTTOM, x = x(tick));
+	svg.text(x(tick), HEIGHT - BOTTOM + 16.0, "middle", &label(tick.abs()));
+    }
+    for (age,sex,val) in &data {
+	let row = ages.iter().position(|a| a == age).unwrap_or(0);
+	let i = sexes.iter().position(|s| s == sex).unwrap_or(0);
+	let end = match i { 0 => x(-val), _ => x(*val) };
+	svg.rect(end.min(center), TOP + height * (row as f64 + 0.1), (end - center).abs(),
+		 height * 0.8, COLORS[i % COLORS.len()]);
+    }
+    svg.categories(&ages, "Age group");
+    svg.text(center, HEIGHT - 6.0, "middle", "Incidence");
+    svg.legend(&sexes.iter().enumerate().map(
+	|(i,sex)| (*sex, COLORS[i % COLORS.len()])
+    ).collect::<Vec<_>>());
+
+    Some(svg.finish())
+
+}
+
+/// The geometries of the TopoJSON `object` in `topology` in the
+/// Mercator projection, filled by their value in `values` (by their
+/// `nis` property) with the colors of the `thresholds`.
+pub fn map(title: &str, thresholds: &[f64], topology: &Value, object: &str,
+	   values: &HashMap<&str,f64>) -> Option<String> {
+
+    let project = |(lon,lat): (f64,f64)| (
+	lon.to_radians(),
+	-(std::f64::consts::FRAC_PI_4 + lat.to_radians() / 2.0).tan().ln()
+    );
+    let shapes = shapes(topology, object)?.into_iter().map(
+	|(nis,rings)| (nis, rings.into_iter().map(
+	    |ring| ring.into_iter().map(project).collect::<Vec<_>>()
+	).collect::<Vec<_>>())
+    ).collect::<Vec<_>>();
+    let points = || shapes.iter().flat_map(|(_,rings)| rings.iter().flatten());
+    let (min_x, max_x) = points().fold((f64::INFINITY, f64::NEG_INFINITY), |(min,max),(x,_)| (min.min(*x), max.max(*x)));
+    let (min_y, max_y) = points().fold((f64::INFINITY, f64::NEG_INFINITY), |(min,max),(_,y)| (min.min(*y), max.max(*y)));
+    if !(max_x > min_x && max_y > min_y) {
+	return None;
+    }
+
+    let scale = f64::min((WIDTH - LEFT - RIGHT) / (max_x - min_x), (HEIGHT - TOP - BOTTOM) / (max_y - min_y));
+    let left = LEFT + (WIDTH - LEFT - RIGHT - scale * (max_x - min_x)) / 2.0;
+    let top = TOP + (HEIGHT - TOP - BOTTOM - scale * (max_y - min_y)) / 2.0;
+    let color = |val: f64| ramp(thresholds.iter().filter(|t| val >= **t).count() as f64
+				/ thresholds.len().max(1) as f64);
+    let mut svg = Canvas::new(title);
+
+    for (nis,rings) in &shapes {
+	let path = rings.iter().map(|ring| ring.iter().enumerate().map(
+	    |(i,(x,y))| format!("{}{:.1},{:.1}", match i { 0 => "M", _ => "L" },
+				left + scale * (x - min_x), top + scale * (y - min_y))
+	).collect::<String>() + "Z").collect::<String>();
+	let fill = values.get(nis.as_str()).filter(|val| val.is_finite())
+	    .map_or_else(|| NO_DATA.to_string(), |val| color(*val));
+	let _ = write!(svg.svg, "<path d=\"{}\" fill=\"{}\" fill-rule=\"evenodd\" stroke=\"white\" \
+				 stroke-width=\"0.5\"/>", path, fill);
+    }
+    svg.swatches("Incidence", &(0..=thresholds.len()).map(|i| (
+	match i {
+	    0 => format!("< {}", label(thresholds[0])),
+	    _ => format!("≥ {}", label(thresholds[i-1]))
+	},
+	ramp(i as f64 / thresholds.len() as f64)
+    )).filter(|_| !thresholds.is_empty()).collect::<Vec<_>>());
+
+    Some(svg.finish())
+
+}
+
+
+/// The rings of the polygons of the TopoJSON `object`, by the `nis`
+/// property of its geometries, in longitude and latitude.
+#[allow(clippy::type_complexity)]
+fn shapes(topology: &Value, object: &str) -> Option<Vec<(String,Vec<Vec<(f64,f64)>>)>> {
+
+    let pair = |value: &Value| Some((value[0].as_f64()?, value[1].as_f64()?));
+    let transform = match (pair(&topology["transform"]["scale"]), pair(&topology["transform"]["translate"])) {
+	(Some(scale), Some(translate)) => Some((scale, translate)),
+	_ => None
+    };
+
+    // Quantized arcs are delta-encoded.
+    let arcs = topology["arcs"].as_array()?.iter().map(|arc| {
+	let mut position = (0.0, 0.0);
+	arc.as_array().into_iter().flatten().filter_map(|point| {
+	    let (x,y) = pair(point)?;
+	    Some(match transform {
+		Some(((sx,sy),(tx,ty))) => {
+		    position = (position.0 + x, position.1 + y);
+		    (position.0 * sx + tx, position.1 * sy + ty)
+		},
+		None => (x, y)
+	    })
+	}).collect::<Vec<_>>()
+    }).collect::<Vec<_>>();
+
+    // Negative indices are the reversed arc !index; consecutive arcs
+    // share their end points.
+    let ring = |indices: &Value| {
+	let mut ring = vec![];
+	for index in indices.as_array()? {
+	    let index = index.as_i64()?;
+	    let mut arc = match index >= 0 {
+		true => arcs.get(index as usize)?.clone(),
+		false => arcs.get(!index as usize)?.iter().rev().cloned().collect()
+	    };
+	    if !ring.is_empty() {
+		arc.remove(0);
+	    }
+	    ring.append(&mut arc);
+	}
+	Some(ring)
+    };
+
+    topology["objects"][object]["geometries"].as_array()?.iter().map(|geometry| {
+	let polygons = match geometry["type"].as_str() {
+	    Some("Polygon") => vec![&geometry["arcs"]],
+	    Some("MultiPolygon") => geometry["arcs"].as_array()?.iter().collect(),
+	    _ => vec![]
+	};
+	Some((geometry["properties"]["nis"].as_str().unwrap_or_default().to_string(),
+	      polygons.into_iter().flat_map(|polygon| polygon.as_array().into_iter().flatten())
+	      .map(ring).collect::<Option<Vec<_>>>()?))
+    }).collect()
+
+}
+
+
+fn estimates(data: &[(String,Series<Estimate>)]) -> impl Iterator<Item = f64> + '_ {
+    data.iter().flat_map(|(_,series)| series.present().flat_map(|(_,e)| vec![e.lower, e.upper]))
+}
+
+fn color_of(data: &CasesData, region: &str) -> &'static str {
+    data.iter().position(|(r,_)| r == region).map_or("gray", |i| COLORS[i % COLORS.len()])
+}
+
+/// The color at `fraction` (0 to 1) of the YlOrRd ramp.
+fn ramp(fraction: f64) -> String {
+    let position = fraction.max(0.0) * (YLORRD.len() - 1) as f64;
+    let i = (position.floor() as usize).min(YLORRD.len() - 2);
+    let t = (position - i as f64).min(1.0);
+    let mix = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * t).round() as u8;
+    let ((r1,g1,b1),(r2,g2,b2)) = (YLORRD[i], YLORRD[i+1]);
+    format!("#{:02x}{:02x}{:02x}", mix(r1, r2), mix(g1, g2), mix(b1, b2))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// A tick label: integers with k/M suffixes, fractions with up to
+/// three decimals.
+fn label(value: f64) -> String {
+    let trim = |v: f64| format!("{}", (v * 1000.0).round() / 1000.0);
+    match value.abs() {
+	v if v >= 1e6 => format!("{}M", trim(value / 1e6)),
+	v if v >= 1e3 => format!("{}k", trim(value / 1e3)),
+	_ => trim(value)
+    }
+}
+
+
+/// The dates along the x axis.
+struct TimeAxis {
+    start: NaiveDate,
+    end: NaiveDate,
+}
+
+impl TimeAxis {
+
+    fn new(start: NaiveDate, end: NaiveDate) -> Self {
+	Self { start, end: end.max(start + Duration::days(1)) }
+    }
+
+    fn map(&self, date: NaiveDate) -> f64 {
+	LEFT + (WIDTH - LEFT - RIGHT) * (date - self.start).num_days() as f64
+	    / (self.end - self.start).num_days() as f64
+    }
+
+    /// The first days of the months in the range, at most about ten.
+    fn ticks(&self) -> Vec<NaiveDate> {
+	let months = |date: NaiveDate| date.year() * 12 + date.month0() as i32;
+	let step = ((months(self.end) - months(self.start)) / 10 + 1) as usize;
+	(months(self.start)+1..=months(self.end)).step_by(step).filter_map(
+	    |m| NaiveDate::from_ymd_opt(m.div_euclid(12), m.rem_euclid(12) as u32 + 1, 1)
+	).collect()
+    }
+
+}
+
+/// A linear or logarithmic value axis.
+struct Axis {
+    min: f64,
+    max: f64,
+    log: bool,
+}
+
+impl Axis {
+
+    /// The axis for a Vega-Lite `scale`, fitted to `values` unless
+    /// the scale has a domain. Linear axes include zero, as in
+    /// Vega-Lite.
+    fn from_scale(scale: &Value, values: &[f64]) -> Self {
+	if let Some([min,max]) = scale["domain"].as_array().map(
+	    |domain| domain.iter().filter_map(Value::as_f64).collect::<Vec<_>>()
+	).as_deref() {
+	    return Self::linear(*min, *max, false);
+	}
+	let log = scale["type"].as_str() == Some("log");
+	let values = values.iter().cloned().filter(|v| v.is_finite() && (!log || *v > 0.0))
+	    .collect::<Vec<_>>();
+	let min = values.iter().cloned().fold(f64::INFINITY, f64::min);
+	let max = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+	match (min.is_finite(), log) {
+	    (false, _) => Self::linear(0.0, 1.0, false),
+	    (true, true) => Self {
+		min: 10f64.powf(min.log10().floor()),
+		max: 10f64.powf(max.log10().ceil().max(min.log10().floor() + 1.0)),
+		log: true
+	    },
+	    (true, false) => Self::linear(min.min(0.0), max.max(0.0), true)
+	}
+    }
+
+    /// A linear axis from `min` to `max`, extended to round ticks if
+    /// `nice`.
+    fn linear(min: f64, max: f64, nice: bool) -> Self {
+	let max = match max > min { true => max, false => min + 1.0 };
+	match nice {
+	    true => {
+		let step = Self::step(min, max);
+		Self { min: (min / step).floor() * step, max: (max / step).ceil() * step, log: false }
+	    },
+	    false => Self { min, max, log: false }
+	}
+    }
+
+    /// A round tick step giving about five ticks.
+    fn step(min: f64, max: f64) -> f64 {
+	let raw = (max - min) / 5.0;
+	let magnitude = 10f64.powf(raw.log10().floor());
+	[1.0, 2.0, 5.0, 10.0].iter().map(|m| m * magnitude).find(|step| *step >= raw)
+	    .unwrap_or(10.0 * magnitude)
+    }
+
+    fn contains(&self, value: f64) -> bool {
+	value.is_finite() && value >= self.min && value <= self.max && (!self.log || value > 0.0)
+    }
+
+    fn map(&self, value: f64) -> f64 {
+	let fraction = match self.log {
+	    true => (value.log10() - self.min.log10()) / (self.max.log10() - self.min.log10()),
+	    false => (value - self.min) / (self.max - self.min)
+	};
+	HEIGHT - BOTTOM - (HEIGHT - TOP - BOTTOM) * fraction
+    }
+
+    fn ticks(&self) -> Vec<f64> {
+	match self.log {
+	    true => {
+		let decades = (self.min.log10().round() as i32)..=(self.max.log10().round() as i32);
+		let multiples: &[f64] = match decades.end() - decades.start() {
+		    0..=2 => &[1.0, 2.0, 5.0],
+		    _ => &[1.0]
+		};
+		decades.flat_map(|d| multiples.iter().map(move |m| m * 10f64.powi(d)))
+		    .filter(|v| *v <= self.max * 1.0001).collect()
+	    },
+	    false => {
+		let step = Self::step(self.min, self.max);
+		let first = (self.min / step).ceil() as i64;
+		let last = (self.max / step).floor() as i64;
+		(first..=last).map(|i| i as f64 * step).collect()
+	    }
+	}
+    }
+
+}
+
+
+/// An SVG image under construction.
+struct Canvas {
+    svg: String,
+}
+
+impl Canvas {
+
+    fn new(title: &str) -> Self {
+	let mut svg = String::new();
+	let _ = write!(svg, "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" \
+			     viewBox=\"0 0 {w} {h}\" font-family=\"Helvetica, Arial, DejaVu Sans, sans-serif\" font-size=\"11\">",
+		       w = WIDTH, h = HEIGHT);
+	let _ = write!(svg, "<rect width=\"100%\" height=\"100%\" fill=\"white\"/>");
+	let _ = write!(svg, "<defs><clipPath id=\"plot\"><rect x=\"{}\" y=\"{}\" width=\"{}\" \
+			     height=\"{}\"/></clipPath></defs>",
+		       LEFT, TOP, WIDTH - LEFT - RIGHT, HEIGHT - TOP - BOTTOM);
+	let _ = write!(svg, "<text x=\"{}\" y=\"{}\" text-anchor=\"middle\" font-size=\"13\" \
+			     font-weight=\"bold\">{}</text>",
+		       WIDTH / 2.0, TOP / 2.0 + 4.0, escape(title));
+	Self { svg }
+    }
+
+    fn finish(mut self) -> String {
+	self.svg.push_str("</svg>");
+	self.svg
+    }
+
+    /// `series` as a line, interrupted where values are missing or
+    /// cannot be drawn on the axis.
+    fn line(&mut self, x: &TimeAxis, y: &Axis, series: &Series, color: &str, dash: Option<&str>) {
+	let mut path = String::new();
+	let mut pen = false;
+	for (date,value) in series.iter() {
+	    match value.filter(|v| v.is_finite() && (!y.log || **v > 0.0)) {
+		Some(value) => {
+		    let _ = write!(path, "{}{:.1},{:.1}", match pen { true => "L", false => "M" },
+				   x.map(date), y.map(*value));
+		    pen = true;
+		},
+		None => pen = false
+	    }
+	}
+	let _ = write!(self.svg, "<path d=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"1.5\"{} \
+				  clip-path=\"url(#plot)\"/>", path, color,
+		       dash.map_or(String::new(), |dash| format!(" stroke-dasharray=\"{}\"", dash)));
+    }
+
+    /// The intervals of `series` as a shaded area.
+    fn band(&mut self, x: &TimeAxis, y: &Axis, series: &Series<Estimate>, color: &str, opacity: f64) {
+	let mut segment: Vec<(f64,f64,f64)> = vec![];
+	let points = series.iter().map(|(date,value)| value.filter(
+	    |e| y.contains(e.lower.max(y.min)) && e.upper.is_finite()
+	).map(|e| (x.map(date), y.map(e.lower.max(y.min)), y.map(e.upper.min(y.max)))));
+	for point in points.chain(std::iter::once(None)) {
+	    match point {
+		Some(point) => segment.push(point),
+		None if !segment.is_empty() => {
+		    let outline = segment.iter().map(|(x,_,upper)| format!("{:.1},{:.1}", x, upper))
+			.chain(segment.iter().rev().map(|(x,lower,_)| format!("{:.1},{:.1}", x, lower)))
+			.collect::<Vec<_>>().join(" ");
+		    let _ = write!(self.svg, "<polygon points=\"{}\" fill=\"{}\" fill-opacity=\"{}\" \
+					      clip-path=\"url(#plot)\"/>", outline, color, opacity);
+		    segment.clear();
+		},
+		None => {}
+	    }
+	}
+    }
+
+    fn rect(&mut self, x: f64, y: f64, width: f64, height: f64, fill: &str) {
+	let _ = write!(self.svg, "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" \
+				  fill=\"{}\"/>", x, y, width, height, fill);
+    }
+
+    fn text(&mut self, x: f64, y: f64, anchor: &str, text: &str) {
+	let _ = write!(self.svg, "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"{}\">{}</text>",
+		       x, y, anchor, escape(text));
+    }
+
+    fn diamond(&mut self, x: f64, y: f64, color: &str) {
+	let _ = write!(self.svg, "<path d=\"M{:.1},{:.1}l5,5l-5,5l-5,-5z\" fill=\"{}\" \
+				  stroke=\"white\"/>", x, y - 5.0, color);
+    }
+
+    /// Horizontal reference lines with their labels.
+    fn refs(&mut self, y: &Axis, refs: &Refs) {
+	for (name,value) in refs.iter().filter(|(_,value)| y.contains(*value)) {
+	    let _ = write!(self.svg, "<line x1=\"{}\" x2=\"{}\" y1=\"{y:.1}\" y2=\"{y:.1}\" stroke=\"red\" \
+				      stroke-opacity=\"0.5\"/>", LEFT, WIDTH - RIGHT, y = y.map(*value));
+	    if let Some(name) = name {
+		let _ = write!(self.svg, "<text x=\"{}\" y=\"{:.1}\" text-anchor=\"middle\" \
+					  fill=\"red\">{}</text>",
+			       (LEFT + WIDTH - RIGHT) / 2.0, y.map(*value) - 3.0, escape(name));
+	    }
+	}
+    }
+
+    fn x_axis(&mut self, x: &TimeAxis) {
+	let bottom = HEIGHT - BOTTOM;
+	let _ = write!(self.svg, "<line x1=\"{}\" x2=\"{}\" y1=\"{b}\" y2=\"{b}\" stroke=\"black\"/>",
+		       LEFT, WIDTH - RIGHT, b = bottom);
+	for (i,date) in x.ticks().into_iter().enumerate() {
+	    let format = match i == 0 || date.month() == 1 { true => "%b %Y", false => "%b" };
+	    let _ = write!(self.svg, "<line x1=\"{x:.1}\" x2=\"{x:.1}\" y1=\"{}\" y2=\"{}\" stroke=\"#ddd\"/>\
+				      <text x=\"{x:.1}\" y=\"{}\" text-anchor=\"middle\">{}</text>",
+			   TOP, bottom + 4.0, bottom + 16.0, date.format(format), x = x.map(date));
+	}
+	let _ = write!(self.svg, "<text x=\"{}\" y=\"{}\" text-anchor=\"middle\" \
+				  font-weight=\"bold\">Date</text>",
+		       (LEFT + WIDTH - RIGHT) / 2.0, HEIGHT - 6.0);
+    }
+
+    /// The y axis on the left, or on the right of the plot (without
+    /// grid lines).
+    fn y_axis(&mut self, y: &Axis, title: &str, color: &str, right: bool) {
+	let (edge, side, anchor) = match right {
+	    false => (LEFT, -1.0, "end"),
+	    true => (WIDTH - RIGHT, 1.0, "start")
+	};
+	let _ = write!(self.svg, "<line x1=\"{e}\" x2=\"{e}\" y1=\"{}\" y2=\"{}\" stroke=\"black\"/>",
+		       TOP, HEIGHT - BOTTOM, e = edge);
+	for tick in y.ticks() {
+	    if !right {
+		let _ = write!(self.svg, "<line x1=\"{}\" x2=\"{}\" y1=\"{y:.1}\" y2=\"{y:.1}\" \
+					  stroke=\"#ddd\"/>", LEFT, WIDTH - RIGHT, y = y.map(tick));
+	    }
+	    let _ = write!(self.svg, "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"{}\">{}</text>",
+			   edge + side * 4.0, y.map(tick) + 4.0, anchor, label(tick));
+	}
+	let (tx, ty) = (edge + side * 50.0, (TOP + HEIGHT - BOTTOM) / 2.0);
+	let _ = write!(self.svg, "<text transform=\"translate({:.1},{:.1}) rotate(-90)\" \
+				  text-anchor=\"middle\" font-weight=\"bold\" fill=\"{}\">{}</text>",
+		       tx, ty, color, escape(title));
+    }
+
+    /// Evenly spaced `rows` from the top of the plot, labeled on the
+    /// left.
+    fn categories(&mut self, rows: &[&str], title: &str) {
+	let height = (HEIGHT - TOP - BOTTOM) / rows.len().max(1) as f64;
+	for (i,row) in rows.iter().enumerate() {
+	    self.text(LEFT - 4.0, TOP + height * (i as f64 + 0.5) + 4.0, "end", row);
+	}
+	let (tx, ty) = (LEFT - 50.0, (TOP + HEIGHT - BOTTOM) / 2.0);
+	let _ = write!(self.svg, "<text transform=\"translate({:.1},{:.1}) rotate(-90)\" \
+				  text-anchor=\"middle\" font-weight=\"bold\">{}</text>",
+		       tx, ty, escape(title));
+    }
+
+    /// A color scale titled `title`, as filled squares with their
+    /// labels right of the plot.
+    fn swatches(&mut self, title: &str, entries: &[(String,String)]) {
+	let left = WIDTH - RIGHT + 15.0;
+	let _ = write!(self.svg, "<text x=\"{}\" y=\"{}\" font-weight=\"bold\">{}</text>",
+		       left, TOP + 4.0, escape(title));
+	for (i,(name,color)) in entries.iter().enumerate() {
+	    let y = TOP + 14.0 + i as f64 * 16.0;
+	    self.rect(left, y, 12.0, 12.0, color);
+	    self.text(left + 18.0, y + 10.0, "start", name);
+	}
+    }
+
+    /// The regions and their colors, right of the plot.
+    fn legend(&mut self, entries: &[(&str,&str)]) {
+	let left = WIDTH - RIGHT + 15.0;
+	let spacing = ((HEIGHT - TOP - BOTTOM) / entries.len().max(1) as f64).min(16.0);
+	for (i,(name,color)) in entries.iter().enumerate() {
+	    let y = TOP + 8.0 + i as f64 * spacing;
+	    let _ = write!(self.svg, "<line x1=\"{}\" x2=\"{}\" y1=\"{y:.1}\" y2=\"{y:.1}\" stroke=\"{}\" \
+				      stroke-width=\"2\"/><text x=\"{}\" y=\"{:.1}\">{}</text>",
+			   left, left + 15.0, color, left + 20.0, y + 4.0, escape(name), y = y);
+	}
+    }
+
+}
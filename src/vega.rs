@@ -0,0 +1,909 @@
+//! A small typed builder for the Vega-Lite specs of the graphs: views
+//! and layers, marks, encoding channels and selections, and the
+//! building blocks shared by the graphs (highlighted lines, hover
+//! rule, reference rules, bands and dual axes).
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use serde_json::{Value,json};
+
+use super::graph::Refs;
+
+
+const SCHEMA: &str = "https://vega.github.io/schema/vega-lite/v4.json";
+
+/// Time unit of the date channels: days, in UTC.
+const DAY: &str = "utcyearmonthdate";
+
+/// Name of the selection bound to the legend.
+pub const HIGHLIGHT: &str = "Highlight";
+
+/// Name of the selection of the date nearest to the pointer.
+pub const HOVER: &str = "Hover";
+
+
+/// A view: the whole chart, or one of its layers.
+#[derive(Serialize,Clone,Debug,Default,PartialEq)]
+pub struct Spec {
+    #[serde(rename = "$schema", skip_serializing_if = "Option::is_none")]
+    schema: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    height: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    width: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<Data>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    projection: Option<Value>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    transform: Vec<Transform>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mark: Option<Mark>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    selection: BTreeMap<&'static str,Selection>,
+    #[serde(skip_serializing_if = "Encoding::is_empty")]
+    encoding: Encoding,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resolve: Option<Resolve>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    layer: Vec<Spec>,
+}
+
+#[derive(Serialize,Clone,Debug,PartialEq)]
+struct Data {
+    values: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<Value>,
+}
+
+#[derive(Serialize,Clone,Debug,PartialEq)]
+#[serde(untagged)]
+enum Transform {
+    Pivot {
+	groupby: Vec<String>,
+	value: String,
+	pivot: String,
+    },
+    Filter {
+	filter: Value,
+    },
+    Lookup {
+	lookup: String,
+	from: Value,
+	#[serde(rename = "as")]
+	name: String,
+    },
+}
+
+#[derive(Serialize,Clone,Debug,PartialEq)]
+struct Resolve {
+    scale: BTreeMap<&'static str,&'static str>,
+}
+
+/// A mark: just its type, or its type with properties.
+#[derive(Serialize,Clone,Debug,PartialEq)]
+#[serde(transparent)]
+pub struct Mark(MarkRepr);
+
+#[derive(Serialize,Clone,Debug,PartialEq)]
+#[serde(untagged)]
+enum MarkRepr {
+    Type(&'static str),
+    Def(MarkDef),
+}
+
+#[derive(Serialize,Clone,Debug,PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct MarkDef {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    color: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    opacity: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    shape: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    filled: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stroke_dash: Option<Vec<f64>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stroke: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stroke_width: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    align: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    font_size: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tooltip: Option<Value>,
+}
+
+/// The encoding channels of a view.
+#[derive(Serialize,Clone,Debug,Default,PartialEq)]
+struct Encoding {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    color: Option<Channel>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    x: Option<Channel>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    y: Option<Channel>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    y2: Option<Channel>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<Channel>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    shape: Option<Channel>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    opacity: Option<Opacity>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tooltip: Vec<Channel>,
+}
+
+/// A field mapped to an encoding channel.
+#[derive(Serialize,Clone,Debug,PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Channel {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    field: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    value: Option<Value>,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    kind: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    time_unit: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scale: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    axis: Option<Axis>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sort: Option<&'static str>,
+}
+
+#[derive(Serialize,Clone,Debug,PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct Axis {
+    /// The title of the channel if None, no title if null.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title_color: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label_expr: Option<&'static str>,
+}
+
+/// A constant opacity, overridden by the first matching condition.
+#[derive(Serialize,Clone,Debug,PartialEq)]
+pub struct Opacity {
+    value: f64,
+    condition: Conditions,
+}
+
+#[derive(Serialize,Clone,Debug,PartialEq)]
+#[serde(untagged)]
+enum Conditions {
+    One(Condition),
+    Many(Vec<Condition>),
+}
+
+#[derive(Serialize,Clone,Debug,PartialEq)]
+struct Condition {
+    value: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    selection: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    test: Option<Value>,
+}
+
+/// An interactive selection.
+#[derive(Serialize,Clone,Debug,PartialEq)]
+pub struct Selection {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bind: Option<Value>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    fields: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    init: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    nearest: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    empty: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    clear: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    on: Option<&'static str>,
+}
+
+
+impl Spec {
+
+    /// A chart filling its container.
+    pub fn chart(title: &str) -> Self {
+	Self {
+	    schema: Some(SCHEMA),
+	    height: Some("container"),
+	    width: Some("container"),
+	    title: Some(title.to_string()),
+	    ..Self::default()
+	}
+    }
+
+    /// A layer, inheriting the data and encoding of its parent.
+    pub fn layer() -> Self {
+	Self::default()
+    }
+
+    pub fn data(mut self, values: Vec<Value>) -> Self {
+	self.data = Some(Data { values: Value::Array(values), format: None });
+	self
+    }
+
+    /// The `feature` object of a TopoJSON `topology` as data.
+    pub fn topojson(mut self, topology: &Value, feature: &str) -> Self {
+	self.data = Some(Data {
+	    values: topology.clone(),
+	    format: Some(json!({"type": "topojson", "feature": feature}))
+	});
+	self
+    }
+
+    /// Draw geographic shapes with the `projection` type.
+    pub fn projection(mut self, projection: &str) -> Self {
+	self.projection = Some(json!({"type": projection}));
+	self
+    }
+
+    /// Pivot the `value` field by the values of `pivot`, grouped by
+    /// `groupby`.
+    pub fn pivot(mut self, pivot: &str, value: &str, groupby: &str) -> Self {
+	self.transform.push(Transform::Pivot {
+	    groupby: vec![groupby.to_string()],
+	    value: value.to_string(),
+	    pivot: pivot.to_string()
+	});
+	self
+    }
+
+    /// Keep the data selected in `selection`.
+    pub fn filter(mut self, selection: &str) -> Self {
+	self.transform.push(Transform::Filter { filter: json!({"selection": selection}) });
+	self
+    }
+
+    /// Add the shape of the `feature` object of `topology` whose `key`
+    /// property matches the field `field`, as the field `name`.
+    pub fn lookup_topojson(mut self, field: &str, topology: &Value, feature: &str,
+			   key: &str, name: &str) -> Self {
+	self.transform.push(Transform::Lookup {
+	    lookup: field.to_string(),
+	    from: json!({
+		"data": {
+		    "values": topology,
+		    "format": {"type": "topojson", "feature": feature}
+		},
+		"key": format!("properties.{}", key)
+	    }),
+	    name: name.to_string()
+	});
+	self
+    }
+
+    pub fn mark(mut self, mark: Mark) -> Self {
+	self.mark = Some(mark);
+	self
+    }
+
+    pub fn select(mut self, name: &'static str, selection: Selection) -> Self {
+	self.selection.insert(name, selection);
+	self
+    }
+
+    pub fn color(mut self, channel: Channel) -> Self {
+	self.encoding.color = Some(channel);
+	self
+    }
+
+    pub fn x(mut self, channel: Channel) -> Self {
+	self.encoding.x = Some(channel);
+	self
+    }
+
+    pub fn y(mut self, channel: Channel) -> Self {
+	self.encoding.y = Some(channel);
+	self
+    }
+
+    pub fn y2(mut self, channel: Channel) -> Self {
+	self.encoding.y2 = Some(channel);
+	self
+    }
+
+    pub fn text(mut self, channel: Channel) -> Self {
+	self.encoding.text = Some(channel);
+	self
+    }
+
+    pub fn shape(mut self, channel: Channel) -> Self {
+	self.encoding.shape = Some(channel);
+	self
+    }
+
+    pub fn opacity(mut self, opacity: Opacity) -> Self {
+	self.encoding.opacity = Some(opacity);
+	self
+    }
+
+    pub fn tooltip(mut self, channels: Vec<Channel>) -> Self {
+	self.encoding.tooltip = channels;
+	self
+    }
+
+    /// Give the layers independent y scales and axes.
+    pub fn independent_y(mut self) -> Self {
+	self.resolve = Some(Resolve { scale: vec![("y", "independent")].into_iter().collect() });
+	self
+    }
+
+    pub fn layer_with(mut self, layer: Spec) -> Self {
+	self.layer.push(layer);
+	self
+    }
+
+}
+
+impl Encoding {
+    fn is_empty(&self) -> bool {
+	self == &Self::default()
+    }
+}
+
+
+impl Mark {
+
+    pub fn new(kind: &'static str) -> Self {
+	Mark(MarkRepr::Type(kind))
+    }
+
+    pub fn line() -> Self { Self::new("line") }
+    pub fn point() -> Self { Self::new("point") }
+    pub fn area() -> Self { Self::new("area") }
+    pub fn rule() -> Self { Self::new("rule") }
+    pub fn text() -> Self { Self::new("text") }
+    pub fn geoshape() -> Self { Self::new("geoshape") }
+    pub fn rect() -> Self { Self::new("rect") }
+    pub fn bar() -> Self { Self::new("bar") }
+
+    fn def(self) -> MarkDef {
+	match self.0 {
+	    MarkRepr::Def(def) => def,
+	    MarkRepr::Type(kind) => MarkDef {
+		kind, color: None, opacity: None, size: None, shape: None,
+		filled: None, stroke_dash: None, stroke: None, stroke_width: None,
+		align: None, font_size: None, tooltip: None
+	    }
+	}
+    }
+
+    fn with(self, f: impl FnOnce(&mut MarkDef)) -> Self {
+	let mut def = self.def();
+	f(&mut def);
+	Mark(MarkRepr::Def(def))
+    }
+
+    pub fn color(self, color: &'static str) -> Self {
+	self.with(|def| def.color = Some(color))
+    }
+
+    pub fn opacity(self, opacity: f64) -> Self {
+	self.with(|def| def.opacity = Some(opacity))
+    }
+
+    pub fn size(self, size: f64) -> Self {
+	self.with(|def| def.size = Some(size))
+    }
+
+    /// A filled point of `shape`.
+    pub fn shape(self, shape: &'static str) -> Self {
+	self.with(|def| { def.shape = Some(shape); def.filled = Some(true); })
+    }
+
+    pub fn dashed(self, dash: f64) -> Self {
+	self.with(|def| def.stroke_dash = Some(vec![dash, dash]))
+    }
+
+    /// Outline the mark in `color`.
+    pub fn stroke(self, color: &'static str, width: f64) -> Self {
+	self.with(|def| { def.stroke = Some(color); def.stroke_width = Some(width); })
+    }
+
+    /// Text aligned to the left of its position, in `size` pixels.
+    pub fn left(self, size: f64) -> Self {
+	self.with(|def| { def.align = Some("left"); def.font_size = Some(size); })
+    }
+
+    /// Show all the fields of the data point in a tooltip.
+    pub fn data_tooltip(self) -> Self {
+	self.with(|def| def.tooltip = Some(json!({"content": "data"})))
+    }
+
+}
+
+
+impl Channel {
+
+    /// `field`, with the type of its parent channel.
+    pub fn field(field: &str) -> Self {
+	Self {
+	    field: Some(field.to_string()),
+	    value: None,
+	    kind: None,
+	    time_unit: None,
+	    title: None,
+	    scale: None,
+	    axis: None,
+	    format: None,
+	    sort: None
+	}
+    }
+
+    /// A constant, e.g. a position in pixels.
+    pub fn value(value: Value) -> Self {
+	Self { field: None, value: Some(value), ..Self::field("") }
+    }
+
+    fn typed(field: &str, kind: &'static str) -> Self {
+	Self { kind: Some(kind), ..Self::field(field) }
+    }
+
+    pub fn nominal(field: &str) -> Self {
+	Self::typed(field, "nominal")
+    }
+
+    pub fn quantitative(field: &str) -> Self {
+	Self::typed(field, "quantitative")
+    }
+
+    pub fn temporal(field: &str) -> Self {
+	Self::typed(field, "temporal")
+    }
+
+    pub fn ordinal(field: &str) -> Self {
+	Self::typed(field, "ordinal")
+    }
+
+    /// `field` as GeoJSON shapes.
+    pub fn geojson(field: &str) -> Self {
+	Self::typed(field, "geojson")
+    }
+
+    /// `field` as dates, by day.
+    pub fn date(field: &str) -> Self {
+	Self { time_unit: Some(DAY), ..Self::temporal(field) }
+    }
+
+    pub fn title(mut self, title: &str) -> Self {
+	self.title = Some(title.to_string());
+	self
+    }
+
+    pub fn scale(mut self, scale: &Value) -> Self {
+	self.scale = Some(scale.clone());
+	self
+    }
+
+    /// An axis titled `title` in `color`.
+    pub fn axis(mut self, title: &str, color: &'static str) -> Self {
+	self.axis = Some(Axis { title: Some(json!(title)), title_color: Some(color), label_expr: None });
+	self
+    }
+
+    pub fn untitled_axis(mut self) -> Self {
+	self.axis = Some(Axis { title: Some(Value::Null), title_color: None, label_expr: None });
+	self
+    }
+
+    /// Label the axis with the absolute values, e.g. for the left
+    /// half of a pyramid.
+    pub fn abs_labels(mut self) -> Self {
+	self.axis = Some(Axis { title: None, title_color: None, label_expr: Some("abs(datum.value)") });
+	self
+    }
+
+    /// Sort the values in "ascending" or "descending" order.
+    pub fn sort(mut self, order: &'static str) -> Self {
+	self.sort = Some(order);
+	self
+    }
+
+    /// d3 number format of the field, e.g. ".3f".
+    pub fn format(mut self, format: &'static str) -> Self {
+	self.format = Some(format);
+	self
+    }
+
+}
+
+
+impl Opacity {
+
+    /// `on` when `selection` is selected, else `off`.
+    pub fn selected(selection: &'static str, on: f64, off: f64) -> Self {
+	Self {
+	    value: off,
+	    condition: Conditions::One(Condition { value: on, selection: Some(selection), test: None })
+	}
+    }
+
+    /// `on` when highlighted in the legend, else `off`.
+    pub fn highlight(on: f64, off: f64) -> Self {
+	Self::selected(HIGHLIGHT, on, off)
+    }
+
+    /// `both` when highlighted and hovered, `hover` when only hovered,
+    /// else `off`.
+    pub fn highlight_hover(both: f64, hover: f64, off: f64) -> Self {
+	Self {
+	    value: off,
+	    condition: Conditions::Many(vec![
+		Condition {
+		    value: both,
+		    selection: None,
+		    test: Some(json!({"and": [{"selection": HIGHLIGHT}, {"selection": HOVER}]}))
+		},
+		Condition { value: hover, selection: Some(HOVER), test: None }
+	    ])
+	}
+    }
+
+}
+
+
+impl Selection {
+
+    fn new(kind: &'static str) -> Self {
+	Self {
+	    kind, bind: None, fields: vec![], init: None,
+	    nearest: None, empty: None, clear: None, on: None
+	}
+    }
+
+    /// Values of `field` toggled by clicking the legend.
+    pub fn legend(field: &str) -> Self {
+	Self { bind: Some(json!("legend")), fields: vec![field.to_string()], ..Self::new("multi") }
+    }
+
+    /// Pan and zoom.
+    pub fn scales() -> Self {
+	Self { bind: Some(json!("scales")), ..Self::new("interval") }
+    }
+
+    /// The integer value of `field`, between 0 and `max`, selected with
+    /// a slider labeled `label`; initially `max`.
+    pub fn slider(field: &str, max: usize, label: &str) -> Self {
+	Self {
+	    bind: Some(json!({"input": "range", "min": 0, "max": max, "step": 1, "name": label})),
+	    fields: vec![field.to_string()],
+	    init: Some(json!({field: max})),
+	    ..Self::new("single")
+	}
+    }
+
+    /// The value of `field` nearest to the pointer.
+    pub fn nearest(field: &str) -> Self {
+	Self {
+	    fields: vec![field.to_string()],
+	    nearest: Some(true),
+	    empty: Some("none"),
+	    clear: Some("mouseout"),
+	    on: Some("mouseover"),
+	    ..Self::new("single")
+	}
+    }
+
+}
+
+
+/// Lines and hover points of `y` by `color`, highlighted by clicking
+/// the legend and zoomable, over their parent's data.
+pub fn highlighted_lines(color: Channel, x: Channel, y: Channel) -> Spec {
+    let field = color.field.clone().unwrap_or_default();
+    Spec::layer().color(color).x(x).y(y)
+	.layer_with(Spec::layer()
+		    .mark(Mark::line())
+		    .select(HIGHLIGHT, Selection::legend(&field))
+		    .select("Grid", Selection::scales())
+		    .opacity(Opacity::highlight(1.0, 0.1)))
+	.layer_with(Spec::layer()
+		    .mark(Mark::point())
+		    .opacity(Opacity::highlight_hover(1.0, 0.2, 0.0)))
+}
+
+/// A vertical rule at the value of `field` nearest to the pointer,
+/// with `tooltip`.
+pub fn hover_rule(field: &str, tooltip: Vec<Channel>) -> Spec {
+    Spec::layer()
+	.mark(Mark::rule().color("gray"))
+	.select(HOVER, Selection::nearest(field))
+	.opacity(Opacity::selected(HOVER, 1.0, 0.0))
+	.tooltip(tooltip)
+}
+
+/// Labeled horizontal red rules at `refs`, on the `y` channel (of the
+/// "Value" field), zoomable with the selection `zoom` if given.
+pub fn reference_rules(refs: &Refs, y: Channel, zoom: Option<&'static str>) -> Spec {
+    let rules = Spec::layer().mark(Mark::rule().color("red").opacity(0.5).size(1.0));
+    Spec::layer()
+	.data(refs.iter().map(|(name,y)| json!({
+	    "Name": name.unwrap_or(""),
+	    "Value": y
+	})).collect())
+	.y(y)
+	.layer_with(match zoom {
+	    Some(zoom) => rules.select(zoom, Selection::scales()),
+	    None => rules
+	})
+	.layer_with(Spec::layer()
+		    .mark(Mark::text().color("red"))
+		    .text(Channel::field("Name")))
+}
+
+/// The label of the value of `field` selected with a slider, in the
+/// top left corner: `labels` are indexed by `field`, and the last one
+/// is selected initially.
+pub fn slider_label(field: &'static str, labels: Vec<String>) -> Spec {
+    let max = labels.len().saturating_sub(1);
+    Spec::layer()
+	.data(labels.into_iter().enumerate().map(|(i,label)| json!({
+	    field: i,
+	    "Label": label
+	})).collect())
+	.select(field, Selection::slider(field, max, &format!("{} ", field)))
+	.mark(Mark::text().left(14.0))
+	.x(Channel::value(json!(0)))
+	.y(Channel::value(json!(0)))
+	.text(Channel::nominal("Label"))
+	.opacity(Opacity::selected(field, 1.0, 0.0))
+}
+
+/// An area between the `lower` and `upper` fields, with `opacity`
+/// when highlighted.
+pub fn band(lower: Channel, upper: &str, opacity: f64) -> Spec {
+    Spec::layer()
+	.mark(Mark::area())
+	.y(lower)
+	.y2(Channel::field(upper))
+	.opacity(Opacity::highlight(opacity, 0.03))
+}
+
+/// `left` and `right` layers over the same x axis, with their own
+/// y axes.
+pub fn dual_axis(left: Spec, right: Spec) -> Spec {
+    Spec::layer().independent_y().layer_with(left).layer_with(right)
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn to_json(spec: &Spec) -> Value {
+	serde_json::to_value(spec).unwrap()
+    }
+
+    #[test]
+    fn empty_layer() {
+	assert_eq!(to_json(&Spec::layer()), json!({}));
+    }
+
+    #[test]
+    fn chart() {
+	assert_eq!(to_json(&Spec::chart("Title").data(vec![json!({"Value": 1})])), json!({
+	    "$schema": "https://vega.github.io/schema/vega-lite/v4.json",
+	    "height": "container",
+	    "width": "container",
+	    "title": "Title",
+	    "data": {"values": [{"Value": 1}]}
+	}));
+    }
+
+    #[test]
+    fn marks() {
+	assert_eq!(to_json(&Spec::layer().mark(Mark::line())), json!({"mark": "line"}));
+	assert_eq!(to_json(&Spec::layer().mark(Mark::line().dashed(4.0))), json!({
+	    "mark": {"type": "line", "strokeDash": [4.0, 4.0]}
+	}));
+	assert_eq!(to_json(&Spec::layer().mark(Mark::point().shape("diamond").size(60.0).data_tooltip())), json!({
+	    "mark": {"type": "point", "shape": "diamond", "filled": true, "size": 60.0,
+		     "tooltip": {"content": "data"}}
+	}));
+    }
+
+    #[test]
+    fn channels() {
+	let scale = json!({"type": "log"});
+	assert_eq!(to_json(&Spec::layer()
+			   .x(Channel::date("Date").title("Date"))
+			   .y(Channel::quantitative("Value").scale(&scale).axis("Count", "red"))
+			   .y2(Channel::field("Upper"))
+			   .tooltip(vec![Channel::quantitative("A").format(".3f")])), json!({
+	    "encoding": {
+		"x": {"field": "Date", "type": "temporal", "timeUnit": "utcyearmonthdate", "title": "Date"},
+		"y": {"field": "Value", "type": "quantitative", "scale": {"type": "log"},
+		      "axis": {"title": "Count", "titleColor": "red"}},
+		"y2": {"field": "Upper"},
+		"tooltip": [{"field": "A", "type": "quantitative", "format": ".3f"}]
+	    }
+	}));
+	assert_eq!(to_json(&Spec::layer().y(Channel::quantitative("Value").untitled_axis())), json!({
+	    "encoding": {"y": {"field": "Value", "type": "quantitative", "axis": {"title": null}}}
+	}));
+	assert_eq!(to_json(&Spec::layer().x(Channel::quantitative("Value").title("Incidence").abs_labels())), json!({
+	    "encoding": {"x": {"field": "Value", "type": "quantitative", "title": "Incidence",
+			       "axis": {"labelExpr": "abs(datum.value)"}}}
+	}));
+    }
+
+    #[test]
+    fn lines() {
+	let scale = json!({"type": "linear"});
+	assert_eq!(to_json(&highlighted_lines(Channel::nominal("Region"), Channel::date("Date"),
+					      Channel::quantitative("Value").scale(&scale))), json!({
+	    "encoding": {
+		"color": {"field": "Region", "type": "nominal"},
+		"x": {"field": "Date", "timeUnit": "utcyearmonthdate", "type": "temporal"},
+		"y": {"field": "Value", "scale": {"type": "linear"}, "type": "quantitative"}
+	    },
+	    "layer": [
+		{
+		    "mark": "line",
+		    "selection": {
+			"Highlight": {"bind": "legend", "type": "multi", "fields": ["Region"]},
+			"Grid": {"bind": "scales", "type": "interval"}
+		    },
+		    "encoding": {
+			"opacity": {"value": 0.1, "condition": {"value": 1.0, "selection": "Highlight"}}
+		    }
+		},
+		{
+		    "mark": "point",
+		    "encoding": {
+			"opacity": {
+			    "value": 0.0,
+			    "condition": [
+				{"value": 1.0, "test": {"and": [{"selection": "Highlight"}, {"selection": "Hover"}]}},
+				{"value": 0.2, "selection": "Hover"}
+			    ]
+			}
+		    }
+		}
+	    ]
+	}));
+    }
+
+    #[test]
+    fn hover() {
+	assert_eq!(to_json(&hover_rule("Date", vec![Channel::temporal("Date")]).pivot("Region", "Value", "Date")), json!({
+	    "transform": [{"groupby": ["Date"], "value": "Value", "pivot": "Region"}],
+	    "mark": {"color": "gray", "type": "rule"},
+	    "selection": {
+		"Hover": {
+		    "nearest": true,
+		    "empty": "none",
+		    "clear": "mouseout",
+		    "type": "single",
+		    "on": "mouseover",
+		    "fields": ["Date"]
+		}
+	    },
+	    "encoding": {
+		"opacity": {"value": 0.0, "condition": {"value": 1.0, "selection": "Hover"}},
+		"tooltip": [{"field": "Date", "type": "temporal"}]
+	    }
+	}));
+    }
+
+    #[test]
+    fn references() {
+	let refs = vec![(Some("Max"), 2.0), (None, 1.0)];
+	assert_eq!(to_json(&reference_rules(&refs, Channel::quantitative("Value"), None)), json!({
+	    "data": {"values": [{"Name": "Max", "Value": 2.0}, {"Name": "", "Value": 1.0}]},
+	    "encoding": {"y": {"field": "Value", "type": "quantitative"}},
+	    "layer": [
+		{"mark": {"color": "red", "opacity": 0.5, "size": 1.0, "type": "rule"}},
+		{"mark": {"type": "text", "color": "red"}, "encoding": {"text": {"field": "Name"}}}
+	    ]
+	}));
+	assert_eq!(to_json(&reference_rules(&refs, Channel::quantitative("Value"), Some("Zoom")))["layer"][0], json!({
+	    "mark": {"color": "red", "opacity": 0.5, "size": 1.0, "type": "rule"},
+	    "selection": {"Zoom": {"bind": "scales", "type": "interval"}}
+	}));
+    }
+
+    #[test]
+    fn maps() {
+	let topology = json!({"type": "Topology", "objects": {}});
+	assert_eq!(to_json(&Spec::layer()
+			   .select("Week", Selection::slider("Week", 3, "Week "))
+			   .filter("Week")
+			   .lookup_topojson("NIS", &topology, "municipalities", "nis", "geo")
+			   .mark(Mark::geoshape().stroke("white", 0.5))
+			   .shape(Channel::geojson("geo"))
+			   .x(Channel::value(json!(10)))), json!({
+	    "selection": {
+		"Week": {
+		    "type": "single",
+		    "fields": ["Week"],
+		    "init": {"Week": 3},
+		    "bind": {"input": "range", "min": 0, "max": 3, "step": 1, "name": "Week "}
+		}
+	    },
+	    "transform": [
+		{"filter": {"selection": "Week"}},
+		{
+		    "lookup": "NIS",
+		    "from": {
+			"data": {
+			    "values": {"type": "Topology", "objects": {}},
+			    "format": {"type": "topojson", "feature": "municipalities"}
+			},
+			"key": "properties.nis"
+		    },
+		    "as": "geo"
+		}
+	    ],
+	    "mark": {"type": "geoshape", "stroke": "white", "strokeWidth": 0.5},
+	    "encoding": {
+		"shape": {"field": "geo", "type": "geojson"},
+		"x": {"value": 10}
+	    }
+	}));
+	assert_eq!(to_json(&slider_label("Week", vec!["a".to_string(), "b".to_string()])), json!({
+	    "data": {"values": [{"Week": 0, "Label": "a"}, {"Week": 1, "Label": "b"}]},
+	    "selection": {
+		"Week": {
+		    "type": "single",
+		    "fields": ["Week"],
+		    "init": {"Week": 1},
+		    "bind": {"input": "range", "min": 0, "max": 1, "step": 1, "name": "Week "}
+		}
+	    },
+	    "mark": {"type": "text", "align": "left", "fontSize": 14.0},
+	    "encoding": {
+		"x": {"value": 0},
+		"y": {"value": 0},
+		"text": {"field": "Label", "type": "nominal"},
+		"opacity": {"value": 0.0, "condition": {"value": 1.0, "selection": "Week"}}
+	    }
+	}));
+    }
+
+    #[test]
+    fn bands_and_axes() {
+	let band = band(Channel::quantitative("Lower"), "Upper", 0.2);
+	assert_eq!(to_json(&band), json!({
+	    "mark": "area",
+	    "encoding": {
+		"y": {"field": "Lower", "type": "quantitative"},
+		"y2": {"field": "Upper"},
+		"opacity": {"value": 0.03, "condition": {"value": 0.2, "selection": "Highlight"}}
+	    }
+	}));
+	assert_eq!(to_json(&dual_axis(Spec::layer().mark(Mark::line()), Spec::layer().mark(Mark::rule()))), json!({
+	    "resolve": {"scale": {"y": "independent"}},
+	    "layer": [{"mark": "line"}, {"mark": "rule"}]
+	}));
+    }
+
+}